@@ -1,17 +1,26 @@
 use std::collections::HashMap;
-use std::fs;
 use std::path::{Path, PathBuf};
+use std::{fmt, fs};
 
-use serde::Deserialize;
+use clap::ValueEnum;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{Error as _, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 use thiserror::Error;
 
 use crate::env::{config_dir, data_dir};
+use crate::filter::{FilterError, parse_duration};
+use crate::fuzzy::Fuzzy;
+use crate::search::{MinimumShouldMatch, Operator, Recency, Sort};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub default_opts: Option<DefaultOptsConfig>,
+    #[serde(default)]
     pub indexes: HashMap<String, IndexConfig>,
+    #[serde(default)]
     pub schema: HashMap<String, SchemaConfig>,
+    #[serde(default)]
     pub tokenizers: HashMap<String, TokenizerConfig>,
 }
 
@@ -42,6 +51,97 @@ impl Config {
             .unwrap_or(10)
     }
 
+    pub fn get_default_search_snippets(&self) -> usize {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.snippets)
+            .unwrap_or(0)
+    }
+
+    pub fn get_default_search_snippet_length(&self) -> usize {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.snippet_length)
+            .unwrap_or(150)
+    }
+
+    pub fn get_default_search_format(&self) -> OutputFormat {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.format)
+            .unwrap_or_default()
+    }
+
+    pub fn get_default_grep_tokenizer(&self) -> Option<String> {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.grep.as_ref())
+            .and_then(|x| x.tokenizer.clone())
+    }
+
+    pub fn get_default_grep_cache(&self) -> bool {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.grep.as_ref())
+            .and_then(|x| x.cache)
+            .unwrap_or(false)
+    }
+
+    pub fn get_default_search_template(&self) -> Option<String> {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.template.clone())
+    }
+
+    pub fn get_default_search_sort(&self) -> Sort {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.sort)
+            .unwrap_or_default()
+    }
+
+    pub fn get_default_search_operator(&self) -> Operator {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.operator)
+            .unwrap_or_default()
+    }
+
+    pub fn get_default_search_minimum_should_match(&self) -> Option<MinimumShouldMatch> {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.minimum_should_match)
+    }
+
+    pub fn get_default_search_recency_enabled(&self) -> bool {
+        self.default_opts
+            .as_ref()
+            .and_then(|x| x.search.as_ref())
+            .and_then(|x| x.recency)
+            .unwrap_or(false)
+    }
+
+    pub fn get_default_search_recency(&self) -> Result<Recency, ConfigError> {
+        let mut recency = Recency::default();
+        let Some(opts) = self.default_opts.as_ref().and_then(|x| x.search.as_ref()) else {
+            return Ok(recency);
+        };
+        if let Some(x) = opts.recency_half_life.as_ref() {
+            recency = recency.set_half_life(parse_duration(x)?);
+        }
+        if let Some(x) = opts.recency_weight {
+            recency = recency.set_weight(x);
+        }
+        Ok(recency)
+    }
+
     pub fn get_schema(&self, name: &str) -> Result<SchemaConfig, ConfigError> {
         self.schema
             .get(name)
@@ -60,23 +160,176 @@ impl Config {
 #[derive(Debug, Deserialize)]
 pub struct DefaultOptsConfig {
     search: Option<DefaultSearchOpts>,
+    grep: Option<DefaultGrepOpts>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DefaultSearchOpts {
     pub index: Option<String>,
     pub limit: Option<usize>,
+    pub snippets: Option<usize>,
+    pub snippet_length: Option<usize>,
+    pub format: Option<OutputFormat>,
+    pub template: Option<String>,
+    pub sort: Option<Sort>,
+    pub operator: Option<Operator>,
+    pub minimum_should_match: Option<MinimumShouldMatch>,
+    pub recency: Option<bool>,
+    pub recency_half_life: Option<String>,
+    pub recency_weight: Option<f32>,
+}
+
+/// Format of the search results written by the command line.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// `title, updated_at, path` followed by snippets
+    #[default]
+    Plain,
+    /// A JSON object with all hits
+    Json,
+    /// A JSON object per line
+    Jsonl,
+    /// Tab-separated values with a header
+    Tsv,
+    /// Comma-separated values with a header
+    Csv,
+    /// Absolute paths, or URLs of documents which are not files
+    Paths,
+    /// Custom layout given by `--template`
+    Template,
+    /// `path:line:column:text` for each matching term, as `grep --vimgrep`
+    Vimgrep,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DefaultGrepOpts {
+    pub tokenizer: Option<String>,
+    pub cache: Option<bool>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "IndexConfigToml")]
+#[non_exhaustive]
 pub struct IndexConfig {
     pub path: Option<PathBuf>,
     pub schema: String,
+    /// Directories of the files, keyed by the source names.
     pub sources: HashMap<String, PathBuf>,
+    /// Commands printing the documents, keyed by the source names.
+    pub commands: HashMap<String, CommandConfig>,
+}
+
+/// `IndexConfig` as written in the config file, whose sources are directories or commands.
+#[derive(Deserialize)]
+struct IndexConfigToml {
+    path: Option<PathBuf>,
+    schema: String,
+    sources: HashMap<String, SourceConfig>,
+}
+
+/// A source written as the path of a directory, or as a table with `kind`, which is `dir` by
+/// default.
+enum SourceConfig {
+    Dir(PathBuf),
+    Command(CommandConfig),
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SourceKind {
+    #[default]
+    Dir,
+    Command,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceTable {
+    #[serde(default)]
+    kind: SourceKind,
+    path: Option<PathBuf>,
+    cmd: Option<Vec<String>>,
+}
+
+impl<'de> Deserialize<'de> for SourceConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SourceVisitor;
+
+        impl<'de> Visitor<'de> for SourceVisitor {
+            type Value = SourceConfig;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a path of a directory, or a table with kind")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(SourceConfig::Dir(PathBuf::from(v)))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let table = SourceTable::deserialize(MapAccessDeserializer::new(map))?;
+                match (table.kind, table.path, table.cmd) {
+                    (SourceKind::Dir, Some(path), None) => Ok(SourceConfig::Dir(path)),
+                    (SourceKind::Dir, None, _) => Err(A::Error::missing_field("path")),
+                    (SourceKind::Command, None, Some(cmd)) => {
+                        Ok(SourceConfig::Command(CommandConfig { cmd }))
+                    }
+                    (SourceKind::Command, _, None) => Err(A::Error::missing_field("cmd")),
+                    (SourceKind::Dir, Some(_), Some(_)) => {
+                        Err(A::Error::custom("cmd is given to a source of kind 'dir'"))
+                    }
+                    (SourceKind::Command, Some(_), Some(_)) => Err(A::Error::custom(
+                        "path is given to a source of kind 'command'",
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(SourceVisitor)
+    }
+}
+
+impl From<IndexConfigToml> for IndexConfig {
+    fn from(value: IndexConfigToml) -> Self {
+        let mut sources = HashMap::new();
+        let mut commands = HashMap::new();
+        for (name, source) in value.sources {
+            match source {
+                SourceConfig::Dir(x) => {
+                    sources.insert(name, x);
+                }
+                SourceConfig::Command(x) => {
+                    commands.insert(name, x);
+                }
+            }
+        }
+        Self {
+            path: value.path,
+            schema: value.schema,
+            sources,
+            commands,
+        }
+    }
+}
+
+/// A source of the documents printed by a command as JSON Lines, in the format read by
+/// `shunbin ingest`. The documents are not files of a directory, so records without `url` are
+/// skipped.
+///
+/// On a full index, the documents of the source which are not printed are deleted. Otherwise
+/// the command prints the documents changed since `{last_indexed}`, and documents are deleted
+/// only by records with `"op": "delete"`.
+#[derive(Clone, Debug)]
+pub struct CommandConfig {
+    /// The program and its arguments. `{last_indexed}` in the arguments is replaced with the
+    /// time of the previous indexing in RFC 3339, or with the Unix epoch on a full index, so
+    /// that the command prints all documents with the same arguments.
+    pub cmd: Vec<String>,
 }
 
 impl IndexConfig {
-    pub fn get_path(&self, index_name: &str) -> anyhow::Result<PathBuf> {
+    pub fn get_path(&self, index_name: &str) -> Result<PathBuf, ConfigError> {
         match self.path.clone() {
             Some(x) => Ok(x),
             None => data_dir().map(|x| x.join("indexes").join(index_name)),
@@ -87,6 +340,8 @@ impl IndexConfig {
 #[derive(Clone, Debug, Deserialize)]
 pub struct SchemaConfig {
     pub fields: FieldsConfig,
+    pub synonyms: Option<SynonymsConfig>,
+    pub fuzzy: Option<FuzzyConfig>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -98,6 +353,57 @@ pub struct FieldsConfig {
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct FieldConfig {
     pub tokenizer: String,
+    #[serde(default)]
+    pub stored: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SynonymsConfig {
+    pub path: PathBuf,
+    pub boost: Option<f32>,
+}
+
+/// Fuzzy matching used by `search --fuzzy`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FuzzyConfig {
+    pub fields: Option<Vec<String>>,
+    /// Maximum edit distance, up to 2.
+    #[serde(default, deserialize_with = "fuzzy_distance")]
+    pub distance: Option<u8>,
+    /// Whether swapping two adjacent characters costs one edit.
+    pub transposition: Option<bool>,
+    /// Minimum number of characters of a token matched fuzzily.
+    pub min_length: Option<usize>,
+}
+
+/// Rejects distances over 2, which tantivy does not build automata for.
+fn fuzzy_distance<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    match Option::<u8>::deserialize(deserializer)? {
+        Some(x) if x > 2 => Err(D::Error::custom(format!(
+            "fuzzy distance must be 0, 1 or 2, but got {}",
+            x
+        ))),
+        x => Ok(x),
+    }
+}
+
+impl From<&FuzzyConfig> for Fuzzy {
+    fn from(value: &FuzzyConfig) -> Self {
+        let mut fuzzy = Fuzzy::default();
+        if let Some(x) = value.fields.clone() {
+            fuzzy = fuzzy.set_fields(x);
+        }
+        if let Some(x) = value.distance {
+            fuzzy = fuzzy.set_distance(x);
+        }
+        if let Some(x) = value.transposition {
+            fuzzy = fuzzy.set_transposition_cost_one(x);
+        }
+        if let Some(x) = value.min_length {
+            fuzzy = fuzzy.set_min_length(x);
+        }
+        fuzzy
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -129,7 +435,7 @@ impl From<SudachiSplitMode> for sudachi::analysis::Mode {
     }
 }
 
-pub fn get_default_config_path() -> anyhow::Result<PathBuf> {
+pub fn get_default_config_path() -> Result<PathBuf, ConfigError> {
     config_dir().map(|x| x.join("config.toml"))
 }
 
@@ -143,4 +449,60 @@ pub enum ConfigError {
     ParseError(#[from] toml::de::Error),
     #[error("Not found default index name")]
     NoDefaultIndexName,
+    #[error("{0}")]
+    InvalidValue(#[from] FilterError),
+    #[error("Failed to detect the home directory")]
+    NoHomeDir,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fuzzy_distance() {
+        let config = toml::from_str::<FuzzyConfig>("distance = 2").unwrap();
+        assert_eq!(config.distance, Some(2));
+        let config = toml::from_str::<FuzzyConfig>("").unwrap();
+        assert_eq!(config.distance, None);
+    }
+
+    #[test]
+    fn reject_fuzzy_distance_over_two() {
+        let e = toml::from_str::<FuzzyConfig>("distance = 3").unwrap_err();
+        assert!(e.message().contains("must be 0, 1 or 2"), "{}", e);
+    }
+
+    #[test]
+    fn parse_sources() {
+        let config = toml::from_str::<IndexConfig>(
+            r#"
+schema = "default"
+sources = { notes = "/notes", wiki = { path = "/wiki" }, docs = { kind = "dir", path = "/docs" }, jira = { kind = "command", cmd = ["export", "{last_indexed}"] } }
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.sources["notes"], PathBuf::from("/notes"));
+        assert_eq!(config.sources["wiki"], PathBuf::from("/wiki"));
+        assert_eq!(config.sources["docs"], PathBuf::from("/docs"));
+        assert_eq!(config.commands["jira"].cmd, ["export", "{last_indexed}"]);
+    }
+
+    #[test]
+    fn reject_invalid_sources() {
+        for (source, message) in [
+            (r#"{ kind = "command" }"#, "missing field `cmd`"),
+            (r#"{ kind = "dir" }"#, "missing field `path`"),
+            (
+                r#"{ kind = "file", path = "/x" }"#,
+                "unknown variant `file`",
+            ),
+            (r#"{ path = "/x", cmd = ["x"] }"#, "cmd is given"),
+            (r#"{ path = "/x", depth = 1 }"#, "unknown field `depth`"),
+        ] {
+            let toml = format!("schema = \"default\"\nsources = {{ x = {} }}", source);
+            let e = toml::from_str::<IndexConfig>(toml.as_str()).unwrap_err();
+            assert!(e.message().contains(message), "{}", e);
+        }
+    }
 }
@@ -0,0 +1,253 @@
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io::{self, BufRead, BufReader, Write, stdout};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::Parser;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use shunbin::{Config, Workspace};
+
+use crate::{Cli, Commands};
+
+const SOCKET_NAME: &str = "daemon.sock";
+
+/// Time to wait for the daemon before searching in the process instead, if the daemon hangs.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A search of the command line sent to the daemon.
+#[derive(Debug, Deserialize, Serialize)]
+struct Request {
+    /// Canonical path of the config file of the command line.
+    config: PathBuf,
+    /// Arguments of the command line including the program name.
+    args: Vec<String>,
+    /// Whether stdout of the command line is a terminal.
+    tty: bool,
+}
+
+/// Output of the search written by the command line as its own.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Response {
+    stdout: String,
+    stderr: String,
+    error: Option<String>,
+    /// The daemon serves another config file, so the command line searches in its process.
+    #[serde(default)]
+    mismatch: bool,
+}
+
+/// The workspace of the config file as of its modification time.
+struct Loaded {
+    modified: Option<SystemTime>,
+    workspace: Arc<Workspace>,
+}
+
+/// Keeps the indexes, the tokenizers and the readers open, and serves searches of the command
+/// line over a Unix socket under `$XDG_RUNTIME_DIR`.
+///
+/// The config file is reloaded on the next search after it is modified.
+pub struct Daemon {
+    config_path: PathBuf,
+    loaded: Mutex<Loaded>,
+}
+
+impl Daemon {
+    pub fn new(config_path: &Path, config: Config) -> anyhow::Result<Self> {
+        let config_path = fs::canonicalize(config_path)?;
+        Ok(Self {
+            loaded: Mutex::new(Loaded {
+                modified: modified_at(config_path.as_path()),
+                workspace: Arc::new(Workspace::new(config)),
+            }),
+            config_path,
+        })
+    }
+
+    /// Listens on the socket until the process is killed.
+    pub fn listen(self) -> anyhow::Result<()> {
+        let dir = runtime_dir()
+            .ok_or_else(|| anyhow::anyhow!("XDG_RUNTIME_DIR is not set for the socket."))?;
+        DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+        let path = dir.join(SOCKET_NAME);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                anyhow::bail!("The daemon is already running on {:?}.", path);
+            }
+            // Left by a daemon which was killed.
+            fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        eprintln!("Listening on {:?}.", path);
+
+        let daemon = Arc::new(self);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let daemon = daemon.clone();
+                    thread::spawn(move || daemon.handle(stream));
+                }
+                Err(e) => error!("Failed to accept a connection, {}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, stream: UnixStream) {
+        let start_at = Instant::now();
+        let mut line = String::new();
+        if let Err(e) = BufReader::new(&stream).read_line(&mut line) {
+            error!("Failed to read the request, {}", e);
+            return;
+        }
+
+        let response = match serde_json::from_str::<Request>(line.as_str()) {
+            Ok(request) => self.search(&request),
+            Err(e) => Response {
+                error: Some(format!("Failed to parse the request, {}", e)),
+                ..Response::default()
+            },
+        };
+        debug!("Served a request in {:?}.", start_at.elapsed());
+
+        let mut writer = &stream;
+        let result = serde_json::to_writer(&mut writer, &response)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(writer));
+        if let Err(e) = result {
+            error!("Failed to respond, {}", e);
+        }
+    }
+
+    fn search(&self, request: &Request) -> Response {
+        if request.config != self.config_path {
+            return Response {
+                mismatch: true,
+                ..Response::default()
+            };
+        }
+
+        let args = match Cli::try_parse_from(request.args.iter()) {
+            Ok(Cli {
+                command: Commands::Search(x),
+                ..
+            }) => x,
+            Ok(_) => {
+                return Response {
+                    error: Some("Only search is served by the daemon.".to_string()),
+                    ..Response::default()
+                };
+            }
+            Err(e) => {
+                return Response {
+                    error: Some(e.to_string()),
+                    ..Response::default()
+                };
+            }
+        };
+
+        let workspace = self.workspace();
+        let mut out = Vec::<u8>::new();
+        let mut err = Vec::<u8>::new();
+        let result = crate::search(&workspace, &args, &mut out, &mut err, request.tty);
+        Response {
+            stdout: String::from_utf8_lossy(&out).to_string(),
+            stderr: String::from_utf8_lossy(&err).to_string(),
+            error: result.err().map(|e| format!("{:#}", e)),
+            mismatch: false,
+        }
+    }
+
+    /// Returns the workspace, reloading the config file if it was modified.
+    fn workspace(&self) -> Arc<Workspace> {
+        let mut loaded = self.loaded.lock().unwrap();
+        let modified = modified_at(self.config_path.as_path());
+        if modified != loaded.modified {
+            match Config::load(self.config_path.as_path()) {
+                Ok(config) => {
+                    eprintln!("Reloaded {:?}.", self.config_path);
+                    loaded.workspace = Arc::new(Workspace::new(config));
+                }
+                Err(e) => eprintln!(
+                    "Failed to reload {:?}, {}. Keep the previous config.",
+                    self.config_path, e
+                ),
+            }
+            loaded.modified = modified;
+        }
+        loaded.workspace.clone()
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+/// Returns the directory for sockets, which is private to the user, if `XDG_RUNTIME_DIR` is set.
+fn runtime_dir() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR").map(|x| PathBuf::from(x).join(env!("CARGO_PKG_NAME")))
+}
+
+/// Sends the search of the command line to the daemon, and writes the output.
+///
+/// Returns false if the daemon is not running, does not respond in time or serves another
+/// config file, to search in the process instead.
+pub fn forward_search(config_path: &Path, is_tty: bool) -> anyhow::Result<bool> {
+    let Some(path) = runtime_dir().map(|x| x.join(SOCKET_NAME)) else {
+        return Ok(false);
+    };
+    let Ok(stream) = UnixStream::connect(&path) else {
+        return Ok(false);
+    };
+    let Some(args) = env::args_os()
+        .map(|x| x.into_string().ok())
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Ok(false);
+    };
+    let request = Request {
+        config: fs::canonicalize(config_path)?,
+        args,
+        tty: is_tty,
+    };
+
+    let response = match send(&stream, &request) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("Failed to search with the daemon, {}", e);
+            return Ok(false);
+        }
+    };
+    if response.mismatch {
+        debug!("The daemon serves another config file.");
+        return Ok(false);
+    }
+
+    eprint!("{}", response.stderr);
+    match stdout().lock().write_all(response.stdout.as_bytes()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        x => x?,
+    }
+    match response.error {
+        Some(e) => Err(anyhow::anyhow!(e)),
+        None => Ok(true),
+    }
+}
+
+fn send(mut stream: &UnixStream, request: &Request) -> anyhow::Result<Response> {
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    stream.set_write_timeout(Some(RESPONSE_TIMEOUT))?;
+    serde_json::to_writer(&mut stream, request)?;
+    writeln!(stream)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(line.as_str())?)
+}
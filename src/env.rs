@@ -1,52 +1,54 @@
 use std::env::{home_dir, var_os};
 use std::path::PathBuf;
 
+use crate::config::ConfigError;
+
 pub const APP_NAME: &str = "shunbin";
 
 #[cfg(unix)]
-pub fn xdg_config_home() -> anyhow::Result<PathBuf> {
+pub fn xdg_config_home() -> Result<PathBuf, ConfigError> {
     match var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
         Some(x) => Ok(x),
         None => home_dir()
             .map(|x| x.join(".config"))
-            .ok_or_else(|| anyhow::anyhow!("Failed to detect config file.")),
+            .ok_or(ConfigError::NoHomeDir),
     }
 }
 
 #[cfg(windows)]
-pub fn xdg_config_home() -> anyhow::Result<PathBuf> {
+pub fn xdg_config_home() -> Result<PathBuf, ConfigError> {
     match var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
         Some(x) => Ok(x),
         None => var_os("LOCALAPPDATA")
             .map(PathBuf::from)
-            .ok_or_else(|| anyhow::anyhow!("Failed to detect config file.")),
+            .ok_or(ConfigError::NoHomeDir),
     }
 }
 
 #[cfg(unix)]
-pub fn xdg_data_home() -> anyhow::Result<PathBuf> {
+pub fn xdg_data_home() -> Result<PathBuf, ConfigError> {
     match var_os("XDG_DATA_HOME").map(PathBuf::from) {
         Some(x) => Ok(x),
         None => home_dir()
             .map(|x| x.join(".local").join("share"))
-            .ok_or_else(|| anyhow::anyhow!("Failed to detect config file.")),
+            .ok_or(ConfigError::NoHomeDir),
     }
 }
 
 #[cfg(windows)]
-pub fn xdg_data_home() -> anyhow::Result<PathBuf> {
+pub fn xdg_data_home() -> Result<PathBuf, ConfigError> {
     match var_os("XDG_DATA_HOME").map(PathBuf::from) {
         Some(x) => Ok(x),
         None => var_os("APPDATA")
             .map(PathBuf::from)
-            .ok_or_else(|| anyhow::anyhow!("Failed to detect config file.")),
+            .ok_or(ConfigError::NoHomeDir),
     }
 }
 
-pub fn data_dir() -> anyhow::Result<PathBuf> {
+pub fn data_dir() -> Result<PathBuf, ConfigError> {
     xdg_data_home().map(|x| x.join(APP_NAME))
 }
 
-pub fn config_dir() -> anyhow::Result<PathBuf> {
+pub fn config_dir() -> Result<PathBuf, ConfigError> {
     xdg_config_home().map(|x| x.join(APP_NAME))
 }
@@ -0,0 +1,73 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+use thiserror::Error;
+
+use crate::config::ConfigError;
+use crate::filter::FilterError;
+
+/// Errors returned by the public API of the library.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+    #[error("Failed to get the index config named '{0}'.")]
+    IndexNotFound(String),
+    #[error("Please specify the index to search, {0}")]
+    NoIndex(ConfigError),
+    #[error("{source}. Run `shunbin index --full` to rebuild the index {path:?}.")]
+    SchemaChanged {
+        path: PathBuf,
+        source: tantivy::TantivyError,
+    },
+    #[error(
+        "The index {path:?} has the schema version {version}, not {}. Run `shunbin index --full` to rebuild the index.",
+        crate::index::SCHEMA_VERSION
+    )]
+    SchemaOutdated { path: PathBuf, version: u32 },
+    #[error("Tokenizer '{0}' is not configured.")]
+    TokenizerNotFound(String),
+    #[error("Failed to load the dictionary {path:?}, {message}")]
+    Dictionary { path: PathBuf, message: String },
+    #[error("Failed to parse the query, {0}")]
+    InvalidQuery(String),
+    #[error("Not found the document '{0}' in the indexes.")]
+    DocumentNotFound(String),
+    #[error("The document '{id}' is not a file but {url}.")]
+    NotFile { id: String, url: String },
+    #[error("Failed to get the absolute path from source '{source_name}' and path '{path}'.")]
+    SourceNotFound { source_name: String, path: String },
+    #[error(
+        "The body field of the index is not stored, so the documents cannot be ingested again \
+         with the body. Run with `--allow-missing-body` to export them without the body."
+    )]
+    BodyNotStored,
+    #[error("Failed to read {path:?}, {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("The command of the source '{0}' is empty.")]
+    EmptyCommand(String),
+    #[error("Failed to run {program:?} of the source '{source_name}', {source}")]
+    RunCommand {
+        program: String,
+        source_name: String,
+        source: io::Error,
+    },
+    #[error("{program:?} of the source '{source_name}' exited with {status}.")]
+    CommandFailed {
+        program: String,
+        source_name: String,
+        status: ExitStatus,
+    },
+    #[error(transparent)]
+    Tantivy(#[from] tantivy::TantivyError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use tantivy::query::{AllQuery, BooleanQuery, EnableScoring, Query};
+use tantivy::schema::{OwnedValue, Schema, Value};
+use tantivy::{DocSet, TERMINATED, TantivyDocument};
+
+use crate::error::Error;
+use crate::filter::Filter;
+use crate::record::Record;
+use crate::search::open_reader;
+
+/// Writes the live documents in all segments matching the filter as JSON Lines, which are
+/// ingested again with `Indexer::ingest`. Returns the number of the documents.
+///
+/// Fails if the body is not stored, unless `allow_missing_body` is set to export the documents
+/// without the body.
+pub fn export(
+    index: &tantivy::Index,
+    filter: &Filter,
+    allow_missing_body: bool,
+    out: &mut impl Write,
+) -> Result<usize, Error> {
+    let schema = index.schema();
+    if !schema
+        .get_field_entry(schema.get_field("body")?)
+        .is_stored()
+    {
+        if !allow_missing_body {
+            return Err(Error::BodyNotStored);
+        }
+        warn!("The body field is not stored, so documents are exported without the body.");
+    }
+
+    let queries = filter.to_queries(&schema)?;
+    let query: Box<dyn Query> = match queries.is_empty() {
+        true => Box::new(AllQuery),
+        false => Box::new(BooleanQuery::intersection(queries)),
+    };
+    let weight = query.weight(EnableScoring::disabled_from_schema(&schema))?;
+
+    let searcher = open_reader(index)?.searcher();
+    let mut count = 0;
+    for segment_reader in searcher.segment_readers() {
+        let store_reader = segment_reader.get_store_reader(1)?;
+        let mut scorer = weight.scorer(segment_reader, 1.0)?;
+        let mut doc_id = scorer.doc();
+        while doc_id != TERMINATED {
+            if !segment_reader.is_deleted(doc_id) {
+                let record = read_record(&schema, &store_reader.get(doc_id)?)?;
+                serde_json::to_writer(&mut *out, &record).map_err(io::Error::from)?;
+                writeln!(out)?;
+                count += 1;
+            }
+            doc_id = scorer.advance();
+        }
+    }
+
+    Ok(count)
+}
+
+fn read_record(schema: &Schema, doc: &TantivyDocument) -> Result<Record, Error> {
+    let text = |name: &str| -> tantivy::Result<Option<String>> {
+        Ok(doc
+            .get_first(schema.get_field(name)?)
+            .and_then(|x| x.as_str().map(String::from))
+            .filter(|x| !x.is_empty()))
+    };
+    let date = |name: &str| -> tantivy::Result<Option<DateTime<Utc>>> {
+        Ok(doc
+            .get_first(schema.get_field(name)?)
+            .and_then(|x| x.as_datetime())
+            .and_then(|t| DateTime::from_timestamp_secs(t.into_timestamp_secs())))
+    };
+    let fields = match doc.get_first(schema.get_field("fields")?) {
+        Some(x) => match serde_json::to_value(OwnedValue::from(x))? {
+            serde_json::Value::Object(x) => x,
+            _ => serde_json::Map::new(),
+        },
+        None => serde_json::Map::new(),
+    };
+
+    // The id is ingested again with the prefix of the source.
+    let source = text("source")?.unwrap_or_default();
+    let id = text("id")?.unwrap_or_default();
+    let id = match id.strip_prefix(format!("{}:", source).as_str()) {
+        Some(x) => x.to_string(),
+        None => id,
+    };
+
+    Ok(Record {
+        id,
+        title: text("title")?.unwrap_or_default(),
+        body: text("body")?,
+        source,
+        path: text("path")?,
+        url: text("url")?,
+        modified_at: date("modified_at")?,
+        updated_at: date("updated_at")?,
+        fields,
+        ..Record::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use crate::config::{FieldConfig, FieldsConfig, SchemaConfig};
+    use crate::index::{Indexer, create_ram_index};
+    use crate::tokenizer::Tokenizers;
+
+    use super::*;
+
+    const RECORDS: &str = r#"{"id":"a.md","title":"Auth","body":"Token flow.","source":"notes","path":"a.md","modified_at":"2026-10-01T00:00:00Z","updated_at":"2026-10-02T00:00:00Z"}
+{"id":"1","title":"Outage","body":"Down for an hour.","source":"slack","url":"https://example.com/1","updated_at":"2026-10-03T00:00:00Z","channel":"incidents","thread":3}
+"#;
+
+    fn index(stored: bool) -> tantivy::Index {
+        let schema_config = SchemaConfig {
+            fields: FieldsConfig {
+                title: None,
+                body: Some(FieldConfig {
+                    tokenizer: "default".to_string(),
+                    stored,
+                }),
+            },
+            synonyms: None,
+            fuzzy: None,
+        };
+        create_ram_index(schema_config, &Tokenizers::new(HashMap::new())).unwrap()
+    }
+
+    fn export_lines(index: &tantivy::Index) -> Vec<String> {
+        let mut out = Vec::<u8>::new();
+        export(index, &Filter::default(), false, &mut out).unwrap();
+        let mut lines = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn ingest_exported_documents() {
+        let sources = HashMap::from([("notes".to_string(), PathBuf::from("/notes"))]);
+        let original = index(true);
+        Indexer::without_timestamps()
+            .ingest(&original, &sources, RECORDS.as_bytes())
+            .unwrap();
+        let lines = export_lines(&original);
+        assert_eq!(lines.len(), 2);
+
+        let copy = index(true);
+        Indexer::without_timestamps()
+            .ingest(&copy, &sources, lines.join("\n").as_bytes())
+            .unwrap();
+        assert_eq!(export_lines(&copy), lines);
+
+        let record = serde_json::from_str::<Record>(lines[0].as_str()).unwrap();
+        assert_eq!(record.id, "1");
+        assert_eq!(record.body.as_deref(), Some("Down for an hour."));
+        assert_eq!(record.fields["channel"], "incidents");
+        assert_eq!(record.fields["thread"], 3);
+
+        let record = serde_json::from_str::<Record>(lines[1].as_str()).unwrap();
+        assert_eq!(record.id, "a.md");
+    }
+
+    #[test]
+    fn reject_body_not_stored() {
+        let index = index(false);
+        let mut out = Vec::<u8>::new();
+        assert!(matches!(
+            export(&index, &Filter::default(), false, &mut out),
+            Err(Error::BodyNotStored)
+        ));
+        assert_eq!(
+            export(&index, &Filter::default(), true, &mut out).unwrap(),
+            0
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use std::ops::Bound;
+
+use chrono::{DateTime, Days, Local, NaiveDate, TimeDelta, Utc};
+use tantivy::Term;
+use tantivy::query::{BooleanQuery, Occur, Query, RangeQuery, RegexQuery, TermQuery};
+use tantivy::schema::{IndexRecordOption, Schema};
+use thiserror::Error;
+
+/// Conditions on the stored fields which documents must satisfy.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Source names, a document matches any of them.
+    pub sources: Vec<String>,
+    /// A prefix or a glob pattern of the path relative to the source.
+    pub path: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+            && self.path.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    /// Builds a query for each condition, all of which are required to match.
+    pub fn to_queries(&self, schema: &Schema) -> tantivy::Result<Vec<Box<dyn Query>>> {
+        let mut queries = Vec::<Box<dyn Query>>::new();
+
+        if !self.sources.is_empty() {
+            let field = schema.get_field("source")?;
+            let subqueries = self
+                .sources
+                .iter()
+                .map(|x| {
+                    let query: Box<dyn Query> = Box::new(TermQuery::new(
+                        Term::from_field_text(field, x),
+                        IndexRecordOption::Basic,
+                    ));
+                    (Occur::Should, query)
+                })
+                .collect();
+            queries.push(Box::new(BooleanQuery::new(subqueries)));
+        }
+
+        if let Some(path) = self.path.as_ref() {
+            let field = schema.get_field("path")?;
+            let path = path.trim_start_matches(['/', '\\']);
+            let pattern = if path.contains(['*', '?']) {
+                glob_to_regex(path)
+            } else {
+                format!("{}.*", escape_regex(path))
+            };
+            queries.push(Box::new(RegexQuery::from_pattern(pattern.as_str(), field)?));
+        }
+
+        if self.since.is_some() || self.until.is_some() {
+            let field = schema.get_field("modified_at")?;
+            let to_term = |x: DateTime<Utc>| {
+                Term::from_field_date_for_search(
+                    field,
+                    tantivy::DateTime::from_timestamp_secs(x.timestamp()),
+                )
+            };
+            queries.push(Box::new(RangeQuery::new(
+                self.since
+                    .map(|x| Bound::Included(to_term(x)))
+                    .unwrap_or(Bound::Unbounded),
+                self.until
+                    .map(|x| Bound::Excluded(to_term(x)))
+                    .unwrap_or(Bound::Unbounded),
+            )));
+        }
+
+        Ok(queries)
+    }
+}
+
+/// Parses the start of a time window.
+///
+/// Accepts a positive duration before now such as `12h`, `7d`, `2w`, `3m` and `1y`, where a
+/// month `m` is 30 days and a year `y` is 365 days, a date such as `2025-01-31`, which means the
+/// start of the day in the local time zone, or an RFC 3339 date and time.
+pub fn parse_since(s: &str) -> Result<DateTime<Utc>, FilterError> {
+    parse_date(s, false)
+}
+
+/// Parses the end of a time window in the same formats as [`parse_since`].
+///
+/// A date means the end of the day, so the day itself is included.
+pub fn parse_until(s: &str) -> Result<DateTime<Utc>, FilterError> {
+    parse_date(s, true)
+}
+
+/// Parses a positive duration such as `12h`, `7d`, `2w`, `3m` and `1y`, in the units of
+/// [`parse_since`].
+pub fn parse_duration(s: &str) -> Result<TimeDelta, FilterError> {
+    parse_relative(s.trim()).ok_or_else(|| FilterError::InvalidDuration(s.to_string()))
+}
+
+fn parse_date(s: &str, end_of_day: bool) -> Result<DateTime<Utc>, FilterError> {
+    let s = s.trim();
+
+    if let Some(delta) = parse_relative(s) {
+        return Ok(Utc::now() - delta);
+    }
+
+    if let Ok(x) = DateTime::parse_from_rfc3339(s) {
+        return Ok(x.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| FilterError::InvalidDate(s.to_string()))?;
+    let date = if end_of_day {
+        date.checked_add_days(Days::new(1))
+            .ok_or_else(|| FilterError::InvalidDate(s.to_string()))?
+    } else {
+        date
+    };
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|x| x.and_local_timezone(Local).earliest())
+        .map(|x| x.with_timezone(&Utc))
+        .ok_or_else(|| FilterError::InvalidDate(s.to_string()))
+}
+
+fn parse_relative(s: &str) -> Option<TimeDelta> {
+    let unit = s.chars().last()?;
+    let n = s[..s.len() - unit.len_utf8()].parse::<i64>().ok()?;
+    if n <= 0 {
+        return None;
+    }
+    match unit {
+        'h' => TimeDelta::try_hours(n),
+        'd' => TimeDelta::try_days(n),
+        'w' => TimeDelta::try_weeks(n),
+        'm' => TimeDelta::try_days(n.checked_mul(30)?),
+        'y' => TimeDelta::try_days(n.checked_mul(365)?),
+        _ => None,
+    }
+}
+
+/// Converts a glob pattern into a regular expression matching the whole path.
+///
+/// `*` and `?` do not match path separators, while `**` matches any number of directories.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            _ => regex.push_str(escape_regex(c.to_string().as_str()).as_str()),
+        }
+    }
+
+    regex
+}
+
+fn escape_regex(s: &str) -> String {
+    regex_syntax::escape(s)
+}
+
+#[derive(Debug, Error)]
+pub enum FilterError {
+    #[error("Invalid date '{0}', expected such as '7d', '2w' or '2025-01-31'")]
+    InvalidDate(String),
+    #[error("Invalid duration '{0}', expected such as '12h', '7d' or '2w'")]
+    InvalidDuration(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_relative_dates() {
+        let now = Utc::now();
+        let since = parse_since("7d").unwrap();
+        assert!((now - TimeDelta::days(7) - since).num_seconds().abs() <= 1);
+        let since = parse_since("3m").unwrap();
+        assert!((now - TimeDelta::days(90) - since).num_seconds().abs() <= 1);
+        assert_eq!(parse_duration("2w").unwrap(), TimeDelta::weeks(2));
+        assert_eq!(parse_duration("12h").unwrap(), TimeDelta::hours(12));
+    }
+
+    #[test]
+    fn reject_non_positive_durations() {
+        assert!(parse_since("-3d").is_err());
+        assert!(parse_since("0d").is_err());
+        assert!(parse_duration("-1h").is_err());
+        assert!(parse_duration("7x").is_err());
+        assert!(parse_duration("d").is_err());
+    }
+
+    #[test]
+    fn parse_dates_in_local_time() {
+        let since = parse_since("2025-01-31").unwrap().with_timezone(&Local);
+        assert_eq!(
+            since.date_naive(),
+            NaiveDate::from_ymd_opt(2025, 1, 31).unwrap()
+        );
+        assert_eq!(since.time(), chrono::NaiveTime::MIN);
+
+        // The end of a window includes the day itself.
+        let until = parse_until("2025-01-31").unwrap().with_timezone(&Local);
+        assert_eq!(
+            until.date_naive(),
+            NaiveDate::from_ymd_opt(2025, 2, 1).unwrap()
+        );
+        assert_eq!(until.time(), chrono::NaiveTime::MIN);
+    }
+
+    #[test]
+    fn parse_rfc3339_dates() {
+        let expected = DateTime::parse_from_rfc3339("2025-01-31T12:00:00Z").unwrap();
+        assert_eq!(parse_since("2025-01-31T21:00:00+09:00").unwrap(), expected);
+        assert_eq!(parse_until("2025-01-31T12:00:00Z").unwrap(), expected);
+        assert!(parse_since("2025-13-01").is_err());
+    }
+
+    #[test]
+    fn convert_globs() {
+        assert_eq!(glob_to_regex("*.md"), "[^/]*\\.md");
+        assert_eq!(glob_to_regex("notes/?.md"), "notes/[^/]\\.md");
+        assert_eq!(glob_to_regex("**/a.md"), "(.*/)?a\\.md");
+        assert_eq!(glob_to_regex("a/**"), "a/.*");
+    }
+
+    #[test]
+    fn escape_only_metacharacters() {
+        assert_eq!(escape_regex("a<b>@c.md"), "a<b>@c\\.md");
+        assert_eq!(escape_regex("(x)[y]"), "\\(x\\)\\[y\\]");
+    }
+}
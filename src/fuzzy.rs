@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+
+use tantivy::query::{
+    BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, PhrasePrefixQuery, PhraseQuery, Query,
+    QueryParser, TermQuery,
+};
+use tantivy::query_grammar::{Delimiter, UserInputAst, UserInputLeaf, UserInputLiteral};
+use tantivy::schema::{Field, IndexRecordOption, Schema};
+use tantivy::{Index, Score, Term};
+
+/// Fuzzy matching of long query terms by edit distance.
+#[derive(Clone, Debug)]
+pub struct Fuzzy {
+    fields: Vec<String>,
+    distance: u8,
+    transposition_cost_one: bool,
+    min_length: usize,
+}
+
+impl Default for Fuzzy {
+    fn default() -> Self {
+        Self {
+            fields: vec!["title".to_string(), "body".to_string()],
+            distance: 1,
+            transposition_cost_one: true,
+            min_length: 4,
+        }
+    }
+}
+
+impl Fuzzy {
+    pub fn set_fields(mut self, fields: Vec<String>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Sets the maximum edit distance, up to 2.
+    pub fn set_distance(mut self, distance: u8) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    /// Counts swapping two adjacent characters as one edit instead of two.
+    pub fn set_transposition_cost_one(mut self, transposition_cost_one: bool) -> Self {
+        self.transposition_cost_one = transposition_cost_one;
+        self
+    }
+
+    /// Sets the minimum number of characters of a token matched fuzzily.
+    ///
+    /// Shorter tokens are matched exactly, since a single edit turns a word of two kanji into
+    /// hundreds of unrelated words.
+    pub fn set_min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+}
+
+/// Removes `*` from the end of words, such as `auth*` and `title:認証*`, so the query parser
+/// accepts them, and returns whether any word is matched by prefix.
+///
+/// Words in quotes and using other query syntax are left as they are.
+pub fn split_prefixes(query: &str) -> (String, bool) {
+    let mut words = Vec::<String>::new();
+    let mut has_prefixes = false;
+    let mut in_quotes = false;
+
+    for word in query.split_whitespace() {
+        let quoted = in_quotes || word.contains('"');
+        in_quotes ^= word.matches('"').count() % 2 == 1;
+
+        let body = word.trim_end_matches(')');
+        let stem = body
+            .trim_start_matches(['+', '-', '('])
+            .rsplit_once(':')
+            .map_or(body.trim_start_matches(['+', '-', '(']), |(_, x)| x);
+        match stem.strip_suffix('*') {
+            Some(x) if !quoted && is_plain_term(x) => {
+                has_prefixes = true;
+                let end = body.len() - 1;
+                words.push(format!("{}{}", &word[..end], &word[end + 1..]));
+            }
+            _ => words.push(word.to_string()),
+        }
+    }
+
+    (words.join(" "), has_prefixes)
+}
+
+fn has_prefix(ast: &UserInputAst) -> bool {
+    match ast {
+        UserInputAst::Clause(clauses) => clauses.iter().any(|(_, x)| has_prefix(x)),
+        UserInputAst::Boost(x, _) => has_prefix(x),
+        UserInputAst::Leaf(x) => {
+            matches!(x.as_ref(), UserInputLeaf::Literal(x) if prefix_stem(x).is_some())
+        }
+    }
+}
+
+/// Returns the word without `*` if it is a word outside quotes ending with `*`.
+fn prefix_stem(literal: &UserInputLiteral) -> Option<&str> {
+    if !matches!(literal.delimiter, Delimiter::None) {
+        return None;
+    }
+    let stem = match literal.prefix {
+        true => Some(literal.phrase.as_str()),
+        false => literal.phrase.strip_suffix('*'),
+    }?;
+    is_plain_term(stem).then_some(stem)
+}
+
+fn is_plain_term(word: &str) -> bool {
+    !word.is_empty()
+        && !word.contains([
+            ':', '"', '\'', '(', ')', '[', ']', '{', '}', '^', '*', '~', '\\',
+        ])
+}
+
+/// Replaces term queries built by the query parser with prefix and fuzzy term queries.
+pub struct TermRewriter<'a> {
+    fuzzy: Option<(HashSet<Field>, &'a Fuzzy)>,
+}
+
+impl<'a> TermRewriter<'a> {
+    pub fn new(index: &Index, fuzzy: Option<&'a Fuzzy>) -> tantivy::Result<Self> {
+        let fuzzy = match fuzzy {
+            Some(x) => Some((fuzzy_fields(&index.schema(), x)?, x)),
+            None => None,
+        };
+        Ok(Self { fuzzy })
+    }
+
+    /// Builds the query from the syntax tree, matching the last token of each word ending with
+    /// `*` by prefix.
+    ///
+    /// The words are found by their positions in the tree, so the same word without `*` is
+    /// still matched as a whole word.
+    pub fn build(
+        &self,
+        query_parser: &QueryParser,
+        ast: UserInputAst,
+        default_occur: Occur,
+    ) -> Box<dyn Query> {
+        if !has_prefix(&ast) {
+            let (query, _) = query_parser.build_query_from_user_input_ast_lenient(ast);
+            return self.rewrite(query);
+        }
+
+        match ast {
+            UserInputAst::Clause(clauses) => Box::new(BooleanQuery::new(
+                clauses
+                    .into_iter()
+                    .map(|(occur, ast)| {
+                        let query = self.build(query_parser, ast, default_occur);
+                        (occur.unwrap_or(default_occur), query)
+                    })
+                    .collect(),
+            )),
+            UserInputAst::Boost(ast, boost) => Box::new(BoostQuery::new(
+                self.build(query_parser, *ast, default_occur),
+                boost as Score,
+            )),
+            UserInputAst::Leaf(leaf) => match *leaf {
+                UserInputLeaf::Literal(mut literal) => {
+                    literal.phrase = prefix_stem(&literal).unwrap_or_default().to_string();
+                    literal.prefix = false;
+                    let ast = UserInputAst::Leaf(Box::new(UserInputLeaf::Literal(literal)));
+                    let (query, _) = query_parser.build_query_from_user_input_ast_lenient(ast);
+                    self.rewrite_with(query, true)
+                }
+                leaf => {
+                    let ast = UserInputAst::Leaf(Box::new(leaf));
+                    let (query, _) = query_parser.build_query_from_user_input_ast_lenient(ast);
+                    self.rewrite(query)
+                }
+            },
+        }
+    }
+
+    /// Rewrites the term queries for fuzzy matching.
+    pub fn rewrite(&self, query: Box<dyn Query>) -> Box<dyn Query> {
+        self.rewrite_with(query, false)
+    }
+
+    fn rewrite_with(&self, query: Box<dyn Query>, prefix: bool) -> Box<dyn Query> {
+        if let Some(x) = query.downcast_ref::<BooleanQuery>() {
+            let subqueries = x
+                .clauses()
+                .iter()
+                .map(|(occur, subquery)| (*occur, self.rewrite_with(subquery.box_clone(), prefix)))
+                .collect();
+            let mut rewritten = BooleanQuery::new(subqueries);
+            rewritten.set_minimum_number_should_match(x.get_minimum_number_should_match());
+            return Box::new(rewritten);
+        }
+
+        if let Some(x) = query.downcast_ref::<TermQuery>()
+            && let Some(rewritten) = self.rewrite_term(x.term(), prefix)
+        {
+            return rewritten;
+        }
+
+        if let Some(x) = query.downcast_ref::<PhraseQuery>()
+            && prefix
+        {
+            return Box::new(PhrasePrefixQuery::new(x.phrase_terms()));
+        }
+
+        query
+    }
+
+    fn rewrite_term(&self, term: &Term, prefix: bool) -> Option<Box<dyn Query>> {
+        let fuzzy = self.fuzzy.as_ref().and_then(|(fields, fuzzy)| {
+            let length = term.value().as_str()?.chars().count();
+            (fields.contains(&term.field()) && length >= fuzzy.min_length).then_some(*fuzzy)
+        });
+
+        match (prefix, fuzzy) {
+            (true, Some(fuzzy)) => Some(Box::new(FuzzyTermQuery::new_prefix(
+                term.clone(),
+                fuzzy.distance,
+                fuzzy.transposition_cost_one,
+            ))),
+            (true, None) => Some(Box::new(FuzzyTermQuery::new_prefix(term.clone(), 0, false))),
+            // Exact matches still score by BM25 and rank above near misses.
+            (false, Some(fuzzy)) => Some(Box::new(BooleanQuery::union(vec![
+                Box::new(TermQuery::new(term.clone(), IndexRecordOption::WithFreqs)),
+                Box::new(FuzzyTermQuery::new(
+                    term.clone(),
+                    fuzzy.distance,
+                    fuzzy.transposition_cost_one,
+                )),
+            ]))),
+            (false, None) => None,
+        }
+    }
+}
+
+fn fuzzy_fields(schema: &Schema, fuzzy: &Fuzzy) -> tantivy::Result<HashSet<Field>> {
+    fuzzy.fields.iter().map(|x| schema.get_field(x)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::collector::DocSetCollector;
+    use tantivy::query_grammar::parse_query_lenient;
+    use tantivy::schema::TEXT;
+    use tantivy::{IndexWriter, doc};
+
+    use super::*;
+
+    fn index(bodies: &[&str]) -> (Index, Field) {
+        let mut builder = Schema::builder();
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer: IndexWriter = index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for x in bodies {
+            writer.add_document(doc!(body => *x)).unwrap();
+        }
+        writer.commit().unwrap();
+        (index, body)
+    }
+
+    fn count(index: &Index, field: Field, query: &str, fuzzy: Option<&Fuzzy>) -> usize {
+        let mut query_parser = QueryParser::for_index(index, vec![field]);
+        query_parser.set_conjunction_by_default();
+        let query = TermRewriter::new(index, fuzzy).unwrap().build(
+            &query_parser,
+            parse_query_lenient(query).0,
+            Occur::Must,
+        );
+        let searcher = index.reader().unwrap().searcher();
+        searcher.search(&query, &DocSetCollector).unwrap().len()
+    }
+
+    #[test]
+    fn split_words_ending_with_asterisk() {
+        assert_eq!(
+            split_prefixes("auth* title:認証* +(log*)"),
+            ("auth title:認証 +(log)".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn leave_quoted_words_and_syntax() {
+        assert_eq!(
+            split_prefixes(r#""auth* flow" a*b* x^2*"#),
+            (r#""auth* flow" a*b* x^2*"#.to_string(), false)
+        );
+    }
+
+    #[test]
+    fn match_by_prefix() {
+        let (index, body) = index(&["authentication", "author", "oauth"]);
+        assert_eq!(count(&index, body, "auth*", None), 2);
+        assert_eq!(count(&index, body, "auth", None), 0);
+    }
+
+    #[test]
+    fn match_only_words_with_asterisk_by_prefix() {
+        let (index, body) = index(&["foo bar", "foobar"]);
+        assert_eq!(count(&index, body, "foo* foo", None), 1);
+        assert_eq!(count(&index, body, "foo foo*", None), 1);
+        assert_eq!(count(&index, body, "foo*", None), 2);
+    }
+
+    #[test]
+    fn match_fuzzily_by_prefix() {
+        let (index, body) = index(&["authentication", "other"]);
+        let fuzzy = Fuzzy::default().set_fields(vec!["body".to_string()]);
+        assert_eq!(count(&index, body, "authen*", Some(&fuzzy)), 1);
+        assert_eq!(count(&index, body, "atuhen*", Some(&fuzzy)), 1);
+        assert_eq!(count(&index, body, "atuhen*", None), 0);
+    }
+}
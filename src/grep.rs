@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use log::debug;
+use tantivy::Index;
+
+use crate::config::{Config, FieldConfig, FieldsConfig, IndexConfig, SchemaConfig};
+use crate::env::data_dir;
+use crate::error::Error;
+use crate::index::{Indexer, create_ram_index, recreate_index};
+use crate::query::Query;
+use crate::search::{SearchOptions, SearchResults, SnippetOptions, open_reader, search};
+use crate::tokenizer::Tokenizers;
+
+/// Name of the index of `Grep`, set to the documents found in it.
+pub const GREP_INDEX_NAME: &str = "grep";
+
+/// Parameters of the 64-bit FNV-1a hash of the name of the cache.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// An index of the files under directories, which are not configured as an index.
+///
+/// The index is built in memory, or kept under the data directory with `set_cache` and updated
+/// with the files modified since the previous build.
+#[derive(Debug)]
+pub struct Grep {
+    dirs: Vec<PathBuf>,
+    tokenizer: String,
+    cache: bool,
+}
+
+impl Grep {
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dirs,
+            tokenizer: "default".to_string(),
+            cache: false,
+        }
+    }
+
+    /// Sets the tokenizer of the title and the body, configured in the config file or built in
+    /// tantivy.
+    pub fn set_tokenizer(mut self, tokenizer: String) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    pub fn set_cache(mut self, cache: bool) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Returns the sources of the documents, keyed by the canonical paths of the directories.
+    ///
+    /// The directories are given as they are, so that the paths of the documents are printed
+    /// relative to them.
+    pub fn sources(&self) -> Result<HashMap<String, PathBuf>, Error> {
+        self.dirs
+            .iter()
+            .map(|dir| Ok((source_name(dir)?, dir.clone())))
+            .collect()
+    }
+
+    /// Returns the config of the index to print the documents, which has no path.
+    pub fn index_config(&self) -> Result<IndexConfig, Error> {
+        Ok(IndexConfig {
+            path: None,
+            schema: String::new(),
+            sources: self.sources()?,
+            commands: HashMap::new(),
+        })
+    }
+
+    /// Builds the index, or updates the cached index.
+    pub fn build(&self, tokenizers: &Tokenizers) -> Result<Index, Error> {
+        // Checked before the cache is created under the data directory.
+        if !tokenizers.contains(self.tokenizer.as_str()) {
+            return Err(Error::TokenizerNotFound(self.tokenizer.clone()));
+        }
+
+        let start_at = Instant::now();
+        // The files are indexed from the canonical paths, so the cache is shared by the calls
+        // from any working directory.
+        let sources = self
+            .sources()?
+            .into_keys()
+            .map(|x| (x.clone(), PathBuf::from(x)))
+            .collect::<HashMap<_, _>>();
+
+        let schema_config = SchemaConfig {
+            fields: FieldsConfig {
+                title: Some(FieldConfig {
+                    tokenizer: self.tokenizer.clone(),
+                    stored: false,
+                }),
+                // Stored to create snippets.
+                body: Some(FieldConfig {
+                    tokenizer: self.tokenizer.clone(),
+                    stored: true,
+                }),
+            },
+            synonyms: None,
+            fuzzy: None,
+        };
+
+        let index = match self.cache {
+            // The cache built by another version is built again.
+            true => recreate_index(self.cache_path(&sources)?, schema_config, tokenizers)?,
+            false => create_ram_index(schema_config, tokenizers)?,
+        };
+
+        let mut indexer = match self.cache {
+            // Files indexed before are skipped only if the cache is not removed.
+            true if open_reader(&index)?.searcher().num_docs() > 0 => Indexer::new(),
+            true => Indexer::new().set_increment(false),
+            false => Indexer::without_timestamps(),
+        };
+        let index_name = self.index_name(&sources);
+        let index_config = IndexConfig {
+            path: None,
+            schema: String::new(),
+            sources,
+            commands: HashMap::new(),
+        };
+        indexer.index(index_name, &index, &index_config)?;
+        debug!(
+            "Indexed {} files for grep in {:?}.",
+            indexer.indexed_count(),
+            start_at.elapsed()
+        );
+
+        Ok(index)
+    }
+
+    /// Searches the index built by `build`, and names the index of the documents
+    /// `GREP_INDEX_NAME`.
+    ///
+    /// Only the text, the limit and the snippets of the query are used, falling back to
+    /// `default_opts.search` in the config file.
+    pub fn search(
+        &self,
+        index: &Index,
+        query: &Query,
+        config: &Config,
+    ) -> Result<SearchResults, Error> {
+        let snippet = match query
+            .snippets
+            .unwrap_or_else(|| config.get_default_search_snippets())
+        {
+            0 => None,
+            count => Some(SnippetOptions {
+                max_chars: query
+                    .snippet_length
+                    .unwrap_or_else(|| config.get_default_search_snippet_length()),
+                count,
+            }),
+        };
+        let limit = query
+            .limit
+            .unwrap_or_else(|| config.get_default_search_limit());
+        let options = SearchOptions::new(limit).set_snippet(snippet);
+        Ok(search(index, query.text.as_str(), &options)?.set_index_name(GREP_INDEX_NAME))
+    }
+
+    /// Returns a name unique to the directories and the tokenizer, to record the time of
+    /// indexing of the cache.
+    fn index_name(&self, sources: &HashMap<String, PathBuf>) -> String {
+        let mut source_names = sources.keys().collect::<Vec<_>>();
+        source_names.sort();
+
+        // The hash must not change between builds of the binary, unlike `DefaultHasher`.
+        let mut hash = FNV_OFFSET_BASIS;
+        for x in source_names
+            .iter()
+            .map(|x| x.as_str())
+            .chain([self.tokenizer.as_str()])
+        {
+            // Separated by NUL, which is in neither paths nor names of tokenizers.
+            for byte in x.bytes().chain([0]) {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+            }
+        }
+        // Prefixed not to be mixed with the indexes in the config file under the data directory.
+        format!("_{}/{:016x}", GREP_INDEX_NAME, hash)
+    }
+
+    fn cache_path(&self, sources: &HashMap<String, PathBuf>) -> Result<PathBuf, Error> {
+        Ok(data_dir()?.join(self.index_name(sources)))
+    }
+}
+
+fn source_name(dir: &Path) -> Result<String, Error> {
+    let path = fs::canonicalize(dir).map_err(|e| Error::Read {
+        path: dir.to_path_buf(),
+        source: e,
+    })?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_cache_by_stable_hash() {
+        let grep = Grep::new(Vec::new());
+        let sources = HashMap::from([("/notes".to_string(), PathBuf::from("/notes"))]);
+        assert_eq!(grep.index_name(&sources), "_grep/bd2c110f59a70086");
+    }
+
+    #[test]
+    fn reject_unknown_tokenizer() {
+        let grep = Grep::new(vec![PathBuf::from(".")])
+            .set_tokenizer("unknown".to_string())
+            .set_cache(true);
+        let result = grep.build(&Tokenizers::new(HashMap::new()));
+        assert!(matches!(result, Err(Error::TokenizerNotFound(x)) if x == "unknown"));
+    }
+}
@@ -1,34 +1,57 @@
 use std::collections::HashMap;
 use std::fs;
-use std::io;
-use std::path::{PathBuf, is_separator};
-use std::sync::Arc;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf, is_separator};
+use std::process::{Command, Stdio};
+use std::time::Instant;
 
-use chrono::{DateTime, Utc};
-use log::warn;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, warn};
 use serde::{Deserialize, Serialize, de};
-use sudachi::dic::dictionary::JapaneseDictionary;
-use sudachi_tantivy::SudachiTokenizer;
 use tantivy::Index;
 use tantivy::TantivyDocument;
 use tantivy::Term;
 use tantivy::directory::MmapDirectory;
-use tantivy::schema::{DateOptions, IndexRecordOption, TextFieldIndexing, TextOptions};
+use tantivy::schema::{
+    DateOptions, IndexRecordOption, JsonObjectOptions, OwnedValue, TextFieldIndexing, TextOptions,
+};
 use tantivy::tokenizer::RawTokenizer;
 
-use crate::config::{FieldConfig, SchemaConfig, TokenizerConfig};
+use crate::config::{CommandConfig, Config, FieldConfig, IndexConfig, SchemaConfig};
 use crate::env::data_dir;
+use crate::error::Error;
 use crate::fs::RecursiveReadDir;
+use crate::record::{Op, Record};
+use crate::tokenizer::Tokenizers;
 
 const RAW_TOKENIZER_NAME: &str = "_raw";
 
+/// Version of the fields created by `create_schema`, increased on changes to them, since tantivy
+/// does not open an index with other fields.
+///
+/// - 1: `title`, `body`, `source`, `path`, `updated_at` and `id`
+/// - 2: `modified_at` is added
+/// - 3: `title` and `path` are fast fields
+/// - 4: `url` and `fields` are added
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// File in the directory of an index with its schema version. An index without it has version 1.
+const SCHEMA_VERSION_FILE: &str = "shunbin_schema_version";
+
 #[derive(Debug)]
 pub struct Indexer {
     tms: Option<TimestampManager>,
     count: usize,
+    deleted_count: usize,
     increment: bool,
 }
 
+impl Default for Indexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Indexer {
     pub fn new() -> Self {
         let tms = match TimestampManager::new() {
@@ -41,22 +64,37 @@ impl Indexer {
         Self {
             tms,
             count: 0,
+            deleted_count: 0,
             increment: true,
         }
     }
 
+    /// Creates an indexer which does not record the time of indexing, for an index which is not
+    /// indexed again.
+    pub fn without_timestamps() -> Self {
+        Self {
+            tms: None,
+            count: 0,
+            deleted_count: 0,
+            increment: false,
+        }
+    }
+
+    /// Indexes the files of the directories and the documents printed by the commands of the
+    /// sources.
     pub fn index(
         &mut self,
         index_name: String,
         index: &tantivy::Index,
-        sources: HashMap<String, PathBuf>,
-    ) -> anyhow::Result<()> {
+        index_config: &IndexConfig,
+    ) -> Result<(), Error> {
         let schema_fields = SchemaFields::from_index(index)?;
         let mut index_writer = index.writer(50_000_000)?;
 
-        sources
+        index_config
+            .sources
             .iter()
-            .try_for_each(|(source_name, source)| -> anyhow::Result<()> {
+            .try_for_each(|(source_name, source)| -> Result<(), Error> {
                 let start_at = Utc::now();
                 let mut count = 0;
 
@@ -81,6 +119,90 @@ impl Indexer {
                 Ok(())
             })?;
 
+        index_config.commands.iter().try_for_each(
+            |(source_name, command)| -> Result<(), Error> {
+                let start_at = Utc::now();
+
+                let last_indexed = self.last_indexed(index_name.clone(), source_name.clone());
+                if last_indexed.is_none() {
+                    // The command prints all documents, so the documents not printed are deleted.
+                    index_writer
+                        .delete_term(Term::from_field_text(schema_fields.source, source_name));
+                }
+                self.index_command(
+                    &mut index_writer,
+                    schema_fields,
+                    source_name,
+                    command,
+                    last_indexed,
+                )?;
+                index_writer.commit()?;
+                self.update_timestamp(index_name.clone(), source_name.clone(), start_at);
+
+                Ok(())
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Runs the command, and indexes the documents of JSON Lines printed by it as the documents
+    /// of the source.
+    fn index_command(
+        &mut self,
+        index_writer: &mut tantivy::IndexWriter,
+        schema: SchemaFields,
+        source_name: &str,
+        command: &CommandConfig,
+        last_indexed: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        // The epoch on a full index, so that the command prints all documents.
+        let last_indexed = last_indexed
+            .unwrap_or(DateTime::UNIX_EPOCH)
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+        let cmd = command
+            .cmd
+            .iter()
+            .map(|x| x.replace("{last_indexed}", last_indexed.as_str()))
+            .collect::<Vec<_>>();
+        let Some((program, args)) = cmd.split_first() else {
+            return Err(Error::EmptyCommand(source_name.to_string()));
+        };
+
+        debug!("Run {:?} for the source '{}'.", cmd, source_name);
+        let mut child = Command::new(program)
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::RunCommand {
+                program: program.clone(),
+                source_name: source_name.to_string(),
+                source: e,
+            })?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // Documents of commands are not files under a source directory.
+        let result = self.ingest_lines(
+            index_writer,
+            schema,
+            &HashMap::new(),
+            BufReader::new(stdout),
+            Some(source_name),
+        );
+        if result.is_err() {
+            // The command may block on writing to the pipe which is no longer read.
+            child.kill().ok();
+        }
+        let status = child.wait()?;
+        result?;
+        if !status.success() {
+            return Err(Error::CommandFailed {
+                program: program.clone(),
+                source_name: source_name.to_string(),
+                status,
+            });
+        }
+
         Ok(())
     }
 
@@ -89,13 +211,13 @@ impl Indexer {
         index: &tantivy::Index,
         sources: HashMap<String, PathBuf>,
         path: PathBuf,
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), Error> {
         let schema_fields = SchemaFields::from_index(index)?;
         let mut index_writer = index.writer(50_000_000)?;
 
         sources
             .iter()
-            .try_for_each(|(source_name, source)| -> anyhow::Result<()> {
+            .try_for_each(|(source_name, source)| -> Result<(), Error> {
                 if self.index_inner(
                     &mut index_writer,
                     schema_fields,
@@ -120,7 +242,7 @@ impl Indexer {
         source_name: String,
         source: PathBuf,
         path: PathBuf,
-    ) -> anyhow::Result<bool> {
+    ) -> Result<bool, Error> {
         let path_string = match path.to_str() {
             Some(s) => s.to_string(),
             None => {
@@ -141,7 +263,14 @@ impl Indexer {
         let id = format!("{}:{}", source_name, relative_path);
         index_writer.delete_term(Term::from_field_text(schema.id, id.as_str()));
 
-        let body = fs::read_to_string(path)?;
+        let read_error = |e| Error::Read {
+            path: path.clone(),
+            source: e,
+        };
+        let modified_at = fs::metadata(&path)
+            .and_then(|x| x.modified())
+            .map_err(read_error)?;
+        let body = fs::read_to_string(&path).map_err(read_error)?;
         if body.is_empty() {
             return Ok(false);
         }
@@ -158,16 +287,148 @@ impl Indexer {
         let now = tantivy::DateTime::from_timestamp_secs(chrono::Utc::now().timestamp());
         doc.add_date(schema.updated_at, now);
 
+        let modified_at = DateTime::<Utc>::from(modified_at);
+        doc.add_date(
+            schema.modified_at,
+            tantivy::DateTime::from_timestamp_secs(modified_at.timestamp()),
+        );
+
         doc.add_text(schema.id, id);
 
         index_writer.add_document(doc)?;
         Ok(true)
     }
 
+    /// Indexes documents of JSON Lines, replacing the documents of the same ids, or deletes the
+    /// documents of the records with `"op": "delete"`.
+    ///
+    /// The ids are prefixed with the sources of the records as `source:id`, as the ids of files.
+    /// Lines which fail to parse, records without a source, and records of paths whose source is
+    /// not one of `sources`, are skipped with warnings.
+    pub fn ingest(
+        &mut self,
+        index: &tantivy::Index,
+        sources: &HashMap<String, PathBuf>,
+        reader: impl BufRead,
+    ) -> Result<(), Error> {
+        let schema_fields = SchemaFields::from_index(index)?;
+        let mut index_writer = index.writer(50_000_000)?;
+
+        self.ingest_lines(&mut index_writer, schema_fields, sources, reader, None)?;
+        index_writer.commit()?;
+
+        Ok(())
+    }
+
+    /// Indexes the records of the lines. The ids of the records are prefixed with the source
+    /// names as the ids of files, so that they do not collide with other sources.
+    ///
+    /// The records of a command are given the name of its source.
+    fn ingest_lines(
+        &mut self,
+        index_writer: &mut tantivy::IndexWriter,
+        schema: SchemaFields,
+        sources: &HashMap<String, PathBuf>,
+        reader: impl BufRead,
+        source_name: Option<&str>,
+    ) -> Result<(), Error> {
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut record = match serde_json::from_str::<Record>(line.as_str()) {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!("Skip line {}, {}", i + 1, e);
+                    continue;
+                }
+            };
+            if let Some(source_name) = source_name {
+                if record.op == Op::Upsert && record.url.is_none() {
+                    warn!(
+                        "Skip {}, records of the command of '{}' need url.",
+                        record.id, source_name
+                    );
+                    continue;
+                }
+                record.source = source_name.to_string();
+            }
+            if record.source.is_empty() {
+                warn!("Skip {}, source is not given.", record.id);
+                continue;
+            }
+            record.id = format!("{}:{}", record.source, record.id);
+            self.ingest_record(index_writer, schema, sources, record)?;
+        }
+
+        Ok(())
+    }
+
+    fn ingest_record(
+        &mut self,
+        index_writer: &mut tantivy::IndexWriter,
+        schema: SchemaFields,
+        sources: &HashMap<String, PathBuf>,
+        record: Record,
+    ) -> Result<(), Error> {
+        // Delete an old document
+        index_writer.delete_term(Term::from_field_text(schema.id, record.id.as_str()));
+        if record.op == Op::Delete {
+            self.deleted_count += 1;
+            return Ok(());
+        }
+        if record.path.is_none() && record.url.is_none() {
+            warn!("Skip {}, neither path nor url is given.", record.id);
+            return Ok(());
+        }
+        // The file of the path is opened from the directory of the source.
+        if record.url.is_none() && !sources.contains_key(record.source.as_str()) {
+            warn!(
+                "Skip {}, the source '{}' of the path is not a directory of the index.",
+                record.id, record.source
+            );
+            return Ok(());
+        }
+
+        let updated_at = record.updated_at.unwrap_or_else(Utc::now);
+        let modified_at = record.modified_at.unwrap_or(updated_at);
+
+        let mut doc = TantivyDocument::default();
+        doc.add_text(schema.title, record.title);
+        doc.add_text(schema.body, record.body.unwrap_or_default());
+        doc.add_text(schema.source, record.source);
+        doc.add_text(schema.path, record.path.unwrap_or_default());
+        if let Some(url) = record.url {
+            doc.add_text(schema.url, url);
+        }
+        doc.add_date(
+            schema.updated_at,
+            tantivy::DateTime::from_timestamp_secs(updated_at.timestamp()),
+        );
+        doc.add_date(
+            schema.modified_at,
+            tantivy::DateTime::from_timestamp_secs(modified_at.timestamp()),
+        );
+        if !record.fields.is_empty() {
+            doc.add_field_value(schema.fields, &OwnedValue::from(record.fields));
+        }
+        doc.add_text(schema.id, record.id);
+
+        index_writer.add_document(doc)?;
+        self.count += 1;
+        Ok(())
+    }
+
     pub fn indexed_count(&self) -> usize {
         self.count
     }
 
+    /// Returns the number of records of the delete operation.
+    pub fn deleted_count(&self) -> usize {
+        self.deleted_count
+    }
+
     pub fn set_increment(mut self, increment: bool) -> Self {
         self.increment = increment;
         self
@@ -184,15 +445,20 @@ impl Indexer {
         index_name: String,
     ) -> io::Result<RecursiveReadDir> {
         let mut read_dir = RecursiveReadDir::new(source)?;
-        if self.increment
-            && let Some(tms) = self.tms.as_ref()
-            && let Some(last_updated_at) = tms.get_timestamp(index_name, source_name)
-        {
+        if let Some(last_updated_at) = self.last_indexed(index_name, source_name) {
             read_dir = read_dir.updated_after(last_updated_at);
         }
         Ok(read_dir)
     }
 
+    /// Returns the time of the previous indexing of the source, or `None` on a full index.
+    fn last_indexed(&self, index_name: String, source_name: String) -> Option<DateTime<Utc>> {
+        match (self.increment, self.tms.as_ref()) {
+            (true, Some(tms)) => tms.get_timestamp(index_name, source_name),
+            _ => None,
+        }
+    }
+
     fn update_timestamp(
         &mut self,
         index_name: String,
@@ -214,50 +480,30 @@ struct SchemaFields {
     body: tantivy::schema::Field,
     source: tantivy::schema::Field,
     path: tantivy::schema::Field,
+    url: tantivy::schema::Field,
     updated_at: tantivy::schema::Field,
+    modified_at: tantivy::schema::Field,
+    fields: tantivy::schema::Field,
     id: tantivy::schema::Field,
 }
 
 impl SchemaFields {
-    fn from_index(index: &tantivy::Index) -> anyhow::Result<Self> {
+    fn from_index(index: &tantivy::Index) -> tantivy::Result<Self> {
         let schema = index.schema();
         Ok(SchemaFields {
             title: schema.get_field("title")?,
             body: schema.get_field("body")?,
             source: schema.get_field("source")?,
             path: schema.get_field("path")?,
+            url: schema.get_field("url")?,
             updated_at: schema.get_field("updated_at")?,
+            modified_at: schema.get_field("modified_at")?,
+            fields: schema.get_field("fields")?,
             id: schema.get_field("id")?,
         })
     }
 }
 
-fn register_tokenizer(
-    index: &tantivy::index::Index,
-    tokenizer_name: &str,
-    config: TokenizerConfig,
-) -> anyhow::Result<()> {
-    match config {
-        TokenizerConfig::Sudachi {
-            dict,
-            user_dict: _,
-            mode,
-        } => {
-            let sudach_config = sudachi::config::Config::new(None, None, Some(dict))?;
-            let jp_dict = JapaneseDictionary::from_cfg(&sudach_config)?;
-            let dict = Arc::new(jp_dict);
-            let mut tokenizer = SudachiTokenizer::new(dict);
-            tokenizer.set_mode(mode.into());
-            index.tokenizers().register(tokenizer_name, tokenizer);
-        }
-        TokenizerConfig::Raw => index
-            .tokenizers()
-            .register(tokenizer_name, RawTokenizer::default()),
-    }
-
-    Ok(())
-}
-
 fn create_text_option(field: &FieldConfig) -> TextOptions {
     let text_index_options =
         TextFieldIndexing::default().set_index_option(IndexRecordOption::WithFreqsAndPositions);
@@ -269,7 +515,12 @@ fn create_text_option(field: &FieldConfig) -> TextOptions {
         text_index_options.set_tokenizer(field.tokenizer.as_str())
     };
 
-    TextOptions::default().set_indexing_options(text_index_options)
+    let text_options = TextOptions::default().set_indexing_options(text_index_options);
+    if field.stored {
+        text_options.set_stored()
+    } else {
+        text_options
+    }
 }
 
 fn create_schema(config: SchemaConfig) -> tantivy::schema::Schema {
@@ -277,7 +528,9 @@ fn create_schema(config: SchemaConfig) -> tantivy::schema::Schema {
 
     scheme_builder.add_text_field(
         "title",
-        create_text_option(&config.fields.title.unwrap_or_default()).set_stored(),
+        create_text_option(&config.fields.title.unwrap_or_default())
+            .set_stored()
+            .set_fast(None),
     );
     scheme_builder.add_text_field(
         "body",
@@ -292,6 +545,13 @@ fn create_schema(config: SchemaConfig) -> tantivy::schema::Schema {
     );
     scheme_builder.add_text_field(
         "path",
+        TextOptions::default()
+            .set_indexing_options(TextFieldIndexing::default().set_tokenizer(RAW_TOKENIZER_NAME))
+            .set_stored()
+            .set_fast(None),
+    );
+    scheme_builder.add_text_field(
+        "url",
         TextOptions::default()
             .set_indexing_options(TextFieldIndexing::default().set_tokenizer(RAW_TOKENIZER_NAME))
             .set_stored(),
@@ -302,6 +562,19 @@ fn create_schema(config: SchemaConfig) -> tantivy::schema::Schema {
             .set_stored()
             .set_fast(),
     );
+    scheme_builder.add_date_field(
+        "modified_at",
+        DateOptions::from(tantivy::schema::INDEXED)
+            .set_stored()
+            .set_fast(),
+    );
+    // Custom fields of the documents ingested from JSON Lines.
+    scheme_builder.add_json_field(
+        "fields",
+        JsonObjectOptions::default()
+            .set_indexing_options(TextFieldIndexing::default().set_tokenizer(RAW_TOKENIZER_NAME))
+            .set_stored(),
+    );
     scheme_builder.add_text_field(
         "id",
         TextOptions::default()
@@ -315,27 +588,115 @@ fn create_schema(config: SchemaConfig) -> tantivy::schema::Schema {
 pub fn create_index(
     index_path: PathBuf,
     schema_config: SchemaConfig,
-    tokenizers: HashMap<String, TokenizerConfig>,
-) -> anyhow::Result<Index> {
+    tokenizers: &Tokenizers,
+) -> Result<Index, Error> {
+    let start_at = Instant::now();
     let schema = create_schema(schema_config);
 
     fs::create_dir_all(&index_path)?;
-    let dir = MmapDirectory::open(index_path)?;
-    let index = Index::open_or_create(dir, schema)?;
+    let exists = index_path.join("meta.json").exists();
+    if exists {
+        let version = read_schema_version(&index_path)?;
+        if version != SCHEMA_VERSION {
+            return Err(Error::SchemaOutdated {
+                path: index_path,
+                version,
+            });
+        }
+    }
 
-    tokenizers
-        .into_iter()
-        .try_for_each(|(name, tokenizer_config)| {
-            register_tokenizer(&index, name.as_str(), tokenizer_config)
-        })?;
+    let dir = MmapDirectory::open(&index_path).map_err(tantivy::TantivyError::from)?;
+    let index = Index::open_or_create(dir, schema).map_err(|e| match e {
+        tantivy::TantivyError::SchemaError(_) => Error::SchemaChanged {
+            path: index_path.clone(),
+            source: e,
+        },
+        _ => Error::from(e),
+    })?;
+    if !exists {
+        fs::write(
+            index_path.join(SCHEMA_VERSION_FILE),
+            SCHEMA_VERSION.to_string(),
+        )?;
+    }
 
     index
         .tokenizers()
         .register(RAW_TOKENIZER_NAME, RawTokenizer::default());
+    tokenizers.register_for(&index)?;
+
+    debug!(
+        "Opened the index {:?} in {:?}.",
+        index_path,
+        start_at.elapsed()
+    );
 
     Ok(index)
 }
 
+/// Opens the index like `create_index`, or removes and creates it again if it was created with
+/// other fields, for a full index which adds all documents again.
+pub fn recreate_index(
+    index_path: PathBuf,
+    schema_config: SchemaConfig,
+    tokenizers: &Tokenizers,
+) -> Result<Index, Error> {
+    match create_index(index_path.clone(), schema_config.clone(), tokenizers) {
+        Err(Error::SchemaOutdated { .. } | Error::SchemaChanged { .. }) => {
+            fs::remove_dir_all(&index_path)?;
+            warn!(
+                "Removed the index {:?} with other fields to rebuild it.",
+                index_path
+            );
+            create_index(index_path, schema_config, tokenizers)
+        }
+        x => x,
+    }
+}
+
+fn read_schema_version(index_path: &Path) -> Result<u32, Error> {
+    let path = index_path.join(SCHEMA_VERSION_FILE);
+    match fs::read_to_string(&path) {
+        Ok(x) => x.trim().parse().map_err(|_| Error::Read {
+            path,
+            source: io::Error::new(io::ErrorKind::InvalidData, "not a schema version"),
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(1),
+        Err(e) => Err(Error::Read { path, source: e }),
+    }
+}
+
+/// Creates an index in memory, which is dropped with the process.
+pub fn create_ram_index(
+    schema_config: SchemaConfig,
+    tokenizers: &Tokenizers,
+) -> Result<Index, Error> {
+    let index = Index::create_in_ram(create_schema(schema_config));
+    index
+        .tokenizers()
+        .register(RAW_TOKENIZER_NAME, RawTokenizer::default());
+    tokenizers.register_for(&index)?;
+    Ok(index)
+}
+
+/// Opens the index named in the config file.
+pub fn open_index(
+    config: &Config,
+    tokenizers: &Tokenizers,
+    index_name: &str,
+) -> Result<Index, Error> {
+    let index_config = config
+        .indexes
+        .get(index_name)
+        .ok_or_else(|| Error::IndexNotFound(index_name.to_string()))?;
+    let schema_config = config.get_schema(index_config.schema.as_str())?;
+    create_index(
+        index_config.get_path(index_name)?,
+        schema_config,
+        tokenizers,
+    )
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct TimestampManager {
     timestamps: HashMap<TimestampKey, DateTime<Utc>>,
@@ -413,3 +774,140 @@ impl<'de> Deserialize<'de> for TimestampKey {
         Ok(TimestampKey(parts[0].to_string(), parts[1].to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::FieldsConfig;
+    use crate::search::{get_document_with, open_reader};
+
+    use super::*;
+
+    fn schema_config() -> SchemaConfig {
+        SchemaConfig {
+            fields: FieldsConfig {
+                title: None,
+                body: None,
+            },
+            synonyms: None,
+            fuzzy: None,
+        }
+    }
+
+    fn index_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("shunbin-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn write_schema_version_of_new_index() {
+        let path = index_path("new-index");
+        let tokenizers = Tokenizers::new(HashMap::new());
+        create_index(path.clone(), schema_config(), &tokenizers).unwrap();
+        assert_eq!(read_schema_version(&path).unwrap(), SCHEMA_VERSION);
+        create_index(path.clone(), schema_config(), &tokenizers).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn rebuild_outdated_index() {
+        let path = index_path("outdated-index");
+        let tokenizers = Tokenizers::new(HashMap::new());
+        create_index(path.clone(), schema_config(), &tokenizers).unwrap();
+        fs::remove_file(path.join(SCHEMA_VERSION_FILE)).unwrap();
+
+        let e = create_index(path.clone(), schema_config(), &tokenizers).unwrap_err();
+        assert!(
+            matches!(e, Error::SchemaOutdated { version: 1, .. }),
+            "{}",
+            e
+        );
+        recreate_index(path.clone(), schema_config(), &tokenizers).unwrap();
+        assert_eq!(read_schema_version(&path).unwrap(), SCHEMA_VERSION);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn skip_paths_of_unknown_sources() {
+        let index = create_ram_index(schema_config(), &Tokenizers::new(HashMap::new())).unwrap();
+        let sources = HashMap::from([("notes".to_string(), PathBuf::from("/notes"))]);
+        let records = r#"{"id":"1","source":"notes","path":"a.md"}
+{"id":"2","source":"wiki","path":"b.md"}
+{"id":"3","source":"wiki","url":"https://example.com/3"}
+"#;
+
+        let mut indexer = Indexer::without_timestamps();
+        indexer
+            .ingest(&index, &sources, records.as_bytes())
+            .unwrap();
+        assert_eq!(indexer.indexed_count(), 2);
+    }
+
+    #[test]
+    fn prefix_ids_with_sources() {
+        let index = create_ram_index(schema_config(), &Tokenizers::new(HashMap::new())).unwrap();
+        let records = r#"{"id":"1","source":"slack","url":"https://example.com/1"}
+{"id":"2","url":"https://example.com/2"}
+"#;
+
+        let mut indexer = Indexer::without_timestamps();
+        indexer
+            .ingest(&index, &HashMap::new(), records.as_bytes())
+            .unwrap();
+        assert_eq!(indexer.indexed_count(), 1);
+        let searcher = open_reader(&index).unwrap().searcher();
+        assert!(
+            get_document_with(&index, &searcher, "slack:1")
+                .unwrap()
+                .is_some()
+        );
+
+        indexer
+            .ingest(
+                &index,
+                &HashMap::new(),
+                r#"{"op":"delete","id":"1","source":"slack"}"#.as_bytes(),
+            )
+            .unwrap();
+        let searcher = open_reader(&index).unwrap().searcher();
+        assert!(
+            get_document_with(&index, &searcher, "slack:1")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ingest_records_of_command_with_url() {
+        let index = create_ram_index(schema_config(), &Tokenizers::new(HashMap::new())).unwrap();
+        let records = r#"{"id":"1","url":"https://example.com/?since={last_indexed}"}
+{"id":"2","path":"a.md"}"#;
+        let index_config = IndexConfig {
+            path: None,
+            schema: String::new(),
+            sources: HashMap::new(),
+            commands: HashMap::from([(
+                "jira".to_string(),
+                CommandConfig {
+                    cmd: vec!["printf".to_string(), records.replace('%', "%%")],
+                },
+            )]),
+        };
+
+        let mut indexer = Indexer::without_timestamps();
+        indexer
+            .index("tickets".to_string(), &index, &index_config)
+            .unwrap();
+        assert_eq!(indexer.indexed_count(), 1);
+
+        let searcher = open_reader(&index).unwrap().searcher();
+        let doc = get_document_with(&index, &searcher, "jira:1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            doc.url.as_deref(),
+            Some("https://example.com/?since=1970-01-01T00:00:00Z")
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Full text search over local notes and documents.
+//!
+//! The indexes are defined in a config file, and searched through a [`Workspace`]:
+//!
+//! ```no_run
+//! use shunbin::{Config, Query, Workspace, get_default_config_path};
+//!
+//! # fn main() -> shunbin::Result<()> {
+//! let config = Config::load(get_default_config_path()?.as_path())?;
+//! let workspace = Workspace::new(config);
+//!
+//! let query = Query::new("kubernetes auth").set_limit(5);
+//! for doc in workspace.search(&query)?.docs {
+//!     println!("{} {}", doc.id, doc.title);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Indexes are built with an [`Indexer`] on an index opened by [`open_index`].
+
+mod config;
+mod env;
+mod error;
+mod export;
+mod filter;
+mod fs;
+mod fuzzy;
+mod grep;
+mod index;
+mod locate;
+mod path;
+mod query;
+mod record;
+mod search;
+mod synonym;
+mod syntax;
+mod tokenizer;
+mod workspace;
+
+pub use crate::config::{
+    CommandConfig, Config, ConfigError, DefaultGrepOpts, DefaultOptsConfig, DefaultSearchOpts,
+    FieldConfig, FieldsConfig, FuzzyConfig, IndexConfig, OutputFormat, SchemaConfig,
+    SudachiSplitMode, SynonymsConfig, TokenizerConfig, get_default_config_path,
+};
+pub use crate::error::{Error, Result};
+pub use crate::export::export;
+pub use crate::filter::{Filter, FilterError, parse_duration, parse_since, parse_until};
+pub use crate::grep::{GREP_INDEX_NAME, Grep};
+pub use crate::index::{Indexer, create_index, create_ram_index, open_index, recreate_index};
+pub use crate::locate::{Location, Locator};
+pub use crate::query::Query;
+pub use crate::record::{Op, Record};
+pub use crate::search::{
+    Doc, MinimumShouldMatch, Operator, QueryExplanation, QueryTerm, Recency, SearchResults,
+    Snippet, Sort,
+};
+pub use crate::syntax::SyntaxWarning;
+pub use crate::tokenizer::Tokenizers;
+pub use crate::workspace::{IndexStats, Workspace};
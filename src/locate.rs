@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use tantivy::tokenizer::{TextAnalyzer, TokenStream};
+
+use crate::error::Error;
+
+/// A position of a query term in a file.
+#[derive(Debug)]
+pub struct Location {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based byte offset in the line, the same as columns of `grep --column` and Vim.
+    pub column: usize,
+    /// Length of the matching term in bytes.
+    pub length: usize,
+    pub text: String,
+}
+
+/// Finds the positions of query terms in files.
+///
+/// Files are tokenized with the tokenizer of the body field, so terms match the same way as in
+/// the index and the offsets point to the original text. Only the terms of the query are
+/// located, so words matched by prefix or fuzzily have no locations.
+#[derive(Clone)]
+pub struct Locator {
+    analyzer: TextAnalyzer,
+    terms: HashSet<String>,
+    max_count: usize,
+}
+
+impl Locator {
+    pub fn new(analyzer: TextAnalyzer, terms: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            analyzer,
+            terms: terms.into_iter().collect(),
+            max_count: usize::MAX,
+        }
+    }
+
+    /// Sets the maximum number of lines located in a text. All terms in a line are located.
+    pub fn set_max_count(mut self, max_count: usize) -> Self {
+        self.max_count = max_count;
+        self
+    }
+
+    pub fn locate_file(&self, path: &Path) -> Result<Vec<Location>, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::Read {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        Ok(self.locate(text.as_str()))
+    }
+
+    pub fn locate(&self, text: &str) -> Vec<Location> {
+        let mut analyzer = self.analyzer.clone();
+        let mut locations = Vec::<Location>::new();
+        let mut lines = 0;
+
+        for (n, line) in text.lines().enumerate() {
+            if lines >= self.max_count {
+                break;
+            }
+
+            let count = locations.len();
+            let mut stream = analyzer.token_stream(line);
+            while let Some(token) = stream.next() {
+                if self.terms.contains(token.text.as_str()) {
+                    locations.push(Location {
+                        line: n + 1,
+                        column: token.offset_from + 1,
+                        length: token.offset_to - token.offset_from,
+                        text: line.to_string(),
+                    });
+                }
+            }
+            if locations.len() > count {
+                lines += 1;
+            }
+        }
+
+        locations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::tokenizer::{LowerCaser, SimpleTokenizer};
+
+    use super::*;
+
+    fn locator(terms: &[&str]) -> Locator {
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .build();
+        Locator::new(analyzer, terms.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn locate_terms() {
+        let locations = locator(&["auth"]).locate("# Auth\nno match\nthe auth service");
+        let positions = locations
+            .iter()
+            .map(|x| (x.line, x.column, x.length))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(1, 3, 4), (3, 5, 4)]);
+        assert_eq!(locations[1].text, "the auth service");
+    }
+
+    #[test]
+    fn locate_cjk_columns_in_bytes() {
+        let locations = locator(&["auth", "認証"]).locate("新しい 認証 auth");
+        let positions = locations
+            .iter()
+            .map(|x| (x.line, x.column, x.length))
+            .collect::<Vec<_>>();
+        // "新しい " is 10 bytes and "認証 " is 7 bytes.
+        assert_eq!(positions, vec![(1, 11, 6), (1, 18, 4)]);
+    }
+
+    #[test]
+    fn max_count_limits_lines() {
+        let text = "auth auth\nauth\nauth";
+        let locations = locator(&["auth"]).set_max_count(2).locate(text);
+        let positions = locations
+            .iter()
+            .map(|x| (x.line, x.column))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(1, 1), (1, 6), (2, 1)]);
+    }
+}
@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+
+use log::{debug, warn};
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+use serde_json::{Value, json};
+use shunbin::{Doc, Sort};
+
+use crate::jsonrpc::{
+    INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR, RpcError, response,
+};
+use crate::serve::{SearchRequest, Server};
+
+/// Characters escaped in the path of a file URI.
+const PATH_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Number of titles offered by completion.
+const COMPLETION_LIMIT: usize = 20;
+
+/// Number of lines of the target shown on hover, after the title.
+const HOVER_LINES: usize = 10;
+
+const SYMBOL_KIND_FILE: u32 = 1;
+const COMPLETION_KIND_FILE: u32 = 17;
+const COMPLETION_KIND_REFERENCE: u32 = 18;
+
+/// A link in a line, with the range in bytes.
+struct Link {
+    start: usize,
+    end: usize,
+    target: String,
+    is_wiki: bool,
+}
+
+/// A Language Server Protocol server over stdio for Markdown notes in the indexes.
+pub struct LspServer {
+    server: Server,
+    /// Texts of the open documents by URI, synchronized in full.
+    documents: HashMap<String, String>,
+}
+
+impl LspServer {
+    pub fn new(server: Server) -> Self {
+        Self {
+            server,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Reads messages framed by `Content-Length` headers, and writes the responses until the
+    /// `exit` notification or the end of the input.
+    pub fn serve(
+        &mut self,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+    ) -> anyhow::Result<()> {
+        while let Some(content) = read_message(&mut reader)? {
+            let message = match serde_json::from_slice::<Value>(content.as_slice()) {
+                Ok(x) => x,
+                Err(e) => {
+                    let error =
+                        RpcError::new(PARSE_ERROR, format!("Failed to parse the message, {}", e));
+                    write_message(&mut writer, &response(Value::Null, Err(error)))?;
+                    continue;
+                }
+            };
+
+            let id = message.get("id").cloned();
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            let Some(method) = message.get("method").and_then(Value::as_str) else {
+                if let Some(id) = id {
+                    let error = RpcError::new(INVALID_REQUEST, "Missing 'method'.");
+                    write_message(&mut writer, &response(id, Err(error)))?;
+                }
+                continue;
+            };
+
+            let Some(id) = id else {
+                debug!("Received the notification {}.", method);
+                if method == "exit" {
+                    break;
+                }
+                self.notify(method, &params);
+                continue;
+            };
+            debug!("Received the request {} {}.", method, id);
+
+            let result = match method {
+                "initialize" => Ok(initialize()),
+                "shutdown" => Ok(Value::Null),
+                "workspace/symbol" => self.workspace_symbol(&params),
+                "textDocument/completion" => self.completion(&params),
+                "textDocument/hover" => self.hover(&params),
+                _ => Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("Unknown method '{}'.", method),
+                )),
+            };
+            write_message(&mut writer, &response(id, result))?;
+        }
+
+        Ok(())
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|x| x.last())
+                    .and_then(|x| x["text"].as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+            }
+            "textDocument/didSave" => {
+                let Some(path) = uri_to_path(uri) else {
+                    return;
+                };
+                match self.server.workspace().index_file(path.as_path()) {
+                    Ok(count) => debug!("Indexed {} documents from {:?}.", count, path),
+                    Err(e) => warn!("Failed to index {:?}, {}", path, e),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Searches the query, and returns the titles of the documents as file symbols.
+    ///
+    /// Documents ingested with URLs are located by the URLs.
+    fn workspace_symbol(&self, params: &Value) -> Result<Value, RpcError> {
+        let query = params["query"].as_str().unwrap_or_default();
+        let docs = self.search(query.to_string())?;
+
+        let symbols = docs
+            .iter()
+            .filter_map(|doc| {
+                let uri = match doc.url.as_ref() {
+                    Some(x) => x.clone(),
+                    None => path_to_uri(self.absolute_path(doc)?.as_path()),
+                };
+                Some(json!({
+                    "name": doc.title,
+                    "kind": SYMBOL_KIND_FILE,
+                    "location": { "uri": uri, "range": range(0, 0, 0) },
+                    "containerName": detail(doc),
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Value::Array(symbols))
+    }
+
+    /// Completes the title of a wiki-link after `[[`, or the path of a Markdown link after `](`.
+    ///
+    /// Markdown links to documents ingested with URLs are completed with the URLs.
+    fn completion(&self, params: &Value) -> Result<Value, RpcError> {
+        let (uri, line_number, character) = position(params)?;
+        let Some(line) = self.line(uri, line_number) else {
+            return Ok(json!([]));
+        };
+        let cursor = byte_index(line.as_str(), character);
+        let (before, after) = line.split_at(cursor);
+
+        let (start, is_wiki) = match (before.rfind("[["), before.rfind("](")) {
+            (Some(x), _) if !before[x..].contains("]]") => (x + 2, true),
+            (_, Some(x)) if !before[x..].contains(')') => (x + 2, false),
+            _ => return Ok(json!([])),
+        };
+        // Paths are matched by the file name, which is often close to the title.
+        let typed = match is_wiki {
+            true => &before[start..],
+            false => before[start..].rsplit('/').next().unwrap_or_default(),
+        };
+        let dir = uri_to_path(uri).and_then(|x| x.parent().map(Path::to_path_buf));
+
+        let query = match typed.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => String::new(),
+            [words @ .., last] => words
+                .iter()
+                .map(|x| format!("title:{}", x))
+                .chain([format!("title:{}*", last)])
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        let docs = self.search(query)?;
+
+        let edit_range = range(line_number, utf16_len(&line[..start]), utf16_len(before));
+        let items = docs
+            .iter()
+            .filter_map(|doc| {
+                let new_text = match is_wiki {
+                    true if after.starts_with("]]") => doc.title.clone(),
+                    true => format!("{}]]", doc.title),
+                    false if doc.url.is_some() => doc.url.clone()?,
+                    false => {
+                        let path = self.absolute_path(doc)?;
+                        let relative = match dir.as_ref() {
+                            Some(x) => relative_path(x, path.as_path()),
+                            None => path,
+                        };
+                        relative.to_string_lossy().replace(' ', "%20")
+                    }
+                };
+                Some(json!({
+                    "label": doc.title,
+                    "kind": if is_wiki { COMPLETION_KIND_REFERENCE } else { COMPLETION_KIND_FILE },
+                    "detail": detail(doc),
+                    "filterText": doc.title,
+                    "textEdit": { "range": edit_range, "newText": new_text },
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({ "isIncomplete": true, "items": items }))
+    }
+
+    /// Shows the title and the first lines of the target of the link under the cursor.
+    fn hover(&self, params: &Value) -> Result<Value, RpcError> {
+        let (uri, line_number, character) = position(params)?;
+        let Some(line) = self.line(uri, line_number) else {
+            return Ok(Value::Null);
+        };
+        let cursor = byte_index(line.as_str(), character);
+        let Some(link) = find_links(line.as_str())
+            .into_iter()
+            .find(|x| x.start <= cursor && cursor < x.end)
+        else {
+            return Ok(Value::Null);
+        };
+
+        let dir = uri_to_path(uri).and_then(|x| x.parent().map(Path::to_path_buf));
+        let Some(doc) = self.resolve_link(&link, dir.as_deref()) else {
+            return Ok(Value::Null);
+        };
+
+        let text = match self.absolute_path(&doc).map(std::fs::read_to_string) {
+            Some(Ok(x)) => x,
+            _ => doc.body.clone().unwrap_or_default(),
+        };
+        let lines = text
+            .lines()
+            .skip(1)
+            .skip_while(|x| x.trim().is_empty())
+            .take(HOVER_LINES)
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("**{}**\n\n{}", doc.title, lines.join("\n")),
+            },
+            "range": range(
+                line_number,
+                utf16_len(&line[..link.start]),
+                utf16_len(&line[..link.end]),
+            ),
+        }))
+    }
+
+    /// Finds the document of a wiki-link by the title or the file name, or of a Markdown link
+    /// by the path relative to the current file.
+    fn resolve_link(&self, link: &Link, dir: Option<&Path>) -> Option<Doc> {
+        if link.is_wiki {
+            let docs = self
+                .search(format!("title:\"{}\"", link.target.replace('"', "")))
+                .ok()?;
+            if let Some(x) = docs
+                .into_iter()
+                .find(|x| x.title.eq_ignore_ascii_case(link.target.as_str()))
+            {
+                return Some(x);
+            }
+            let path = normalize(&dir?.join(format!("{}.md", link.target)));
+            return self.server.workspace().find_file(path.as_path()).ok();
+        }
+
+        let path = PathBuf::from(link.target.as_str());
+        let path = match path.is_absolute() {
+            true => path,
+            false => dir?.join(path),
+        };
+        self.server
+            .workspace()
+            .find_file(normalize(&path).as_path())
+            .ok()
+    }
+
+    fn search(&self, query: String) -> Result<Vec<Doc>, RpcError> {
+        // Without a query, offer the recently modified documents.
+        let sort = match query.trim().is_empty() {
+            true => Sort::Modified,
+            false => Sort::Relevance,
+        };
+        let request = SearchRequest {
+            query,
+            limit: Some(COMPLETION_LIMIT),
+            snippets: Some(0),
+            sort: Some(sort),
+            ..SearchRequest::default()
+        };
+        match self.server.search(&request) {
+            Ok(x) => Ok(x.docs),
+            Err(e) => Err(RpcError::new(INVALID_PARAMS, e.to_string())),
+        }
+    }
+
+    fn absolute_path(&self, doc: &Doc) -> Option<PathBuf> {
+        let index_config = self.server.config().indexes.get(doc.index.as_str())?;
+        doc.absolute_path(&index_config.sources).ok()
+    }
+
+    /// Returns a line of the open document, or of the file if it is not open.
+    fn line(&self, uri: &str, line_number: usize) -> Option<String> {
+        let text = match self.documents.get(uri) {
+            Some(x) => x.clone(),
+            None => std::fs::read_to_string(uri_to_path(uri)?).ok()?,
+        };
+        text.lines().nth(line_number).map(String::from)
+    }
+}
+
+/// Returns the URL of the document, or the path relative to its source.
+fn detail(doc: &Doc) -> String {
+    match doc.url.as_ref() {
+        Some(x) => x.clone(),
+        None => doc.path.to_string_lossy().to_string(),
+    }
+}
+
+fn initialize() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": {
+                "openClose": true,
+                "change": 1,
+                "save": { "includeText": false },
+            },
+            "workspaceSymbolProvider": true,
+            "completionProvider": { "triggerCharacters": ["[", "("] },
+            "hoverProvider": true,
+        },
+        "serverInfo": { "name": "shunbin", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn read_message(reader: &mut impl BufRead) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let length = length.ok_or_else(|| anyhow::anyhow!("Missing the Content-Length header."))?;
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+    Ok(Some(content))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> anyhow::Result<()> {
+    let content = message.to_string();
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn position(params: &Value) -> Result<(&str, usize, usize), RpcError> {
+    let uri = params["textDocument"]["uri"].as_str();
+    let line = params["position"]["line"].as_u64();
+    let character = params["position"]["character"].as_u64();
+    match (uri, line, character) {
+        (Some(uri), Some(line), Some(character)) => Ok((uri, line as usize, character as usize)),
+        _ => Err(RpcError::new(
+            INVALID_PARAMS,
+            "Missing the text document position.",
+        )),
+    }
+}
+
+fn range(line: usize, start: usize, end: usize) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+/// Finds wiki-links such as `[[Title]]` and `[[Title|alias]]`, and Markdown links to local
+/// files such as `[text](../a.md)`.
+fn find_links(line: &str) -> Vec<Link> {
+    let mut links = Vec::<Link>::new();
+
+    let mut from = 0;
+    while let Some(start) = line[from..].find("[[").map(|x| x + from) {
+        let Some(end) = line[start..].find("]]").map(|x| x + start + 2) else {
+            break;
+        };
+        let inner = &line[start + 2..end - 2];
+        let target = inner.split(['|', '#']).next().unwrap_or_default().trim();
+        links.push(Link {
+            start,
+            end,
+            target: target.to_string(),
+            is_wiki: true,
+        });
+        from = end;
+    }
+
+    let mut from = 0;
+    while let Some(middle) = line[from..].find("](").map(|x| x + from) {
+        let (Some(start), Some(end)) = (
+            line[..middle].rfind('['),
+            line[middle..].find(')').map(|x| x + middle + 1),
+        ) else {
+            break;
+        };
+        let target = line[middle + 2..end - 1]
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .trim_matches(['<', '>', ' ']);
+        if !target.is_empty() && !target.contains("://") && !target.starts_with("mailto:") {
+            links.push(Link {
+                start,
+                end,
+                target: percent_decode_str(target).decode_utf8_lossy().to_string(),
+                is_wiki: false,
+            });
+        }
+        from = end;
+    }
+
+    links
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    Some(PathBuf::from(
+        percent_decode_str(path).decode_utf8().ok()?.as_ref(),
+    ))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    format!(
+        "file://{}",
+        utf8_percent_encode(path.to_string_lossy().as_ref(), PATH_SET)
+    )
+}
+
+/// Resolves `.` and `..` without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            x => normalized.push(x),
+        }
+    }
+    normalized
+}
+
+/// Returns the path of `path` relative to the directory `dir`.
+fn relative_path(dir: &Path, path: &Path) -> PathBuf {
+    let dir = dir.components().collect::<Vec<_>>();
+    let path = path.components().collect::<Vec<_>>();
+    let common = dir
+        .iter()
+        .zip(path.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    (common..dir.len()).for_each(|_| relative.push(".."));
+    path[common..].iter().for_each(|x| relative.push(x));
+    relative
+}
+
+/// Converts a position in UTF-16 code units, as counted by LSP, to a byte index in the line.
+fn byte_index(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use shunbin::{Config, Indexer, Tokenizers, Workspace, create_ram_index};
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+[indexes.tickets]
+schema = "default"
+sources = {}
+
+[schema.default]
+fields = { body = { tokenizer = "default", stored = true }, title = { tokenizer = "default" } }
+"#;
+
+    fn server() -> LspServer {
+        let config = toml::from_str::<Config>(CONFIG).unwrap();
+        let schema_config = config.get_schema("default").unwrap();
+        let index = create_ram_index(schema_config, &Tokenizers::new(HashMap::new())).unwrap();
+        Indexer::without_timestamps()
+            .ingest(
+                &index,
+                &HashMap::new(),
+                r#"{"id":"1","source":"docs","title":"Deploy","body":"Rolling upgrade.","url":"https://example.com/1"}"#
+                    .as_bytes(),
+            )
+            .unwrap();
+
+        let workspace = Workspace::new(config);
+        workspace.set_index("tickets", index).unwrap();
+        LspServer::new(Server::new(workspace))
+    }
+
+    fn links(line: &str) -> Vec<(usize, usize, String, bool)> {
+        find_links(line)
+            .into_iter()
+            .map(|x| (x.start, x.end, x.target, x.is_wiki))
+            .collect()
+    }
+
+    #[test]
+    fn find_wiki_links() {
+        assert_eq!(
+            links("see [[Auth design]] and [[Deploy|the deploy#steps]]"),
+            vec![
+                (4, 19, "Auth design".to_string(), true),
+                (24, 51, "Deploy".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            links("[[Auth#Flow]] [[unclosed"),
+            vec![(0, 13, "Auth".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn find_markdown_links_to_files() {
+        assert_eq!(
+            links("[a](../a.md) [b](<b c.md#top>) [認証](%E8%AA%8D%E8%A8%BC.md)"),
+            vec![
+                (0, 12, "../a.md".to_string(), false),
+                (13, 30, "b c.md".to_string(), false),
+                (31, 62, "認証.md".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_links_to_urls() {
+        assert!(
+            links("[site](https://example.com) [mail](mailto:a@example.com) [top](#top)")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn convert_utf16_positions_to_byte_indexes() {
+        let line = "a認証😀b";
+        assert_eq!(byte_index(line, 0), 0);
+        assert_eq!(byte_index(line, 1), 1);
+        assert_eq!(byte_index(line, 2), 4);
+        assert_eq!(byte_index(line, 3), 7);
+        // The emoji takes two UTF-16 code units and four bytes.
+        assert_eq!(byte_index(line, 5), 11);
+        assert_eq!(byte_index(line, 100), line.len());
+    }
+
+    #[test]
+    fn read_messages_by_content_length() {
+        let mut reader = "Content-Length: 2\r\nContent-Type: application/json\r\n\r\n{}content-length:7\r\n\r\n{\"a\":1}"
+            .as_bytes();
+        assert_eq!(read_message(&mut reader).unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(b"{\"a\":1}".to_vec())
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn reject_messages_without_content_length() {
+        let mut reader = "Content-Type: application/json\r\n\r\n{}".as_bytes();
+        assert!(read_message(&mut reader).is_err());
+    }
+
+    #[test]
+    fn write_and_read_messages() {
+        let mut buffer = Vec::<u8>::new();
+        write_message(&mut buffer, &json!({ "text": "認証" })).unwrap();
+        let content = read_message(&mut buffer.as_slice()).unwrap().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&content).unwrap(),
+            json!({ "text": "認証" })
+        );
+    }
+
+    #[test]
+    fn locate_symbols_of_urls() {
+        let symbols = server()
+            .workspace_symbol(&json!({ "query": "deploy" }))
+            .unwrap();
+        assert_eq!(symbols[0]["name"], "Deploy");
+        assert_eq!(symbols[0]["location"]["uri"], "https://example.com/1");
+        assert_eq!(symbols[0]["containerName"], "https://example.com/1");
+    }
+
+    #[test]
+    fn complete_markdown_links_with_urls() {
+        let mut server = server();
+        let uri = "file:///notes/a.md";
+        server
+            .documents
+            .insert(uri.to_string(), "see [the deploy](Dep".to_string());
+
+        let params = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 0, "character": 20 },
+        });
+        let items = server.completion(&params).unwrap();
+        assert_eq!(items["items"][0]["label"], "Deploy");
+        assert_eq!(
+            items["items"][0]["textEdit"]["newText"],
+            "https://example.com/1"
+        );
+    }
+}
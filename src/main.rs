@@ -1,19 +1,33 @@
-mod config;
-mod env;
-mod fs;
-mod index;
-mod path;
-mod search;
+#[cfg(unix)]
+mod daemon;
+mod jsonrpc;
+mod lsp;
+mod mcp;
+mod output;
+mod serve;
+mod tui;
 
-use std::path::PathBuf;
-use std::process;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write, stderr, stdin, stdout};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+use chrono::{DateTime, TimeDelta, Utc};
 use clap::{Args, Parser, Subcommand};
-use log::{debug, error, warn};
+use log::{debug, warn};
+use shunbin::{
+    Config, Doc, Filter, GREP_INDEX_NAME, Grep, Indexer, Locator, MinimumShouldMatch, Operator,
+    OutputFormat, Query, Sort, Tokenizers, Workspace, create_index, export,
+    get_default_config_path, open_index, parse_duration, parse_since, parse_until, recreate_index,
+};
 
-use crate::config::{Config, get_default_config_path};
-use crate::index::{Indexer, create_index};
-use crate::search::search;
+#[cfg(unix)]
+use crate::daemon::Daemon;
+use crate::lsp::LspServer;
+use crate::mcp::McpServer;
+use crate::output::Printer;
+use crate::serve::Server;
 
 #[derive(Parser)]
 #[command(version)]
@@ -23,8 +37,14 @@ struct Cli {
 
     #[arg(long, short = 'c', global = true)]
     config: Option<PathBuf>,
+
+    /// Search in this process even if the daemon is running.
+    #[arg(long, global = true)]
+    no_daemon: bool,
 }
 
+// Commands are parsed only once, so the size of the variants does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     Index {
@@ -34,17 +54,271 @@ enum Commands {
         #[command(flatten)]
         index_mode: IndexMode,
     },
-    Search {
+    /// Index documents of JSON Lines, one object per line with `id`, `source`, `title`, `body`,
+    /// and `path` or `url`. Documents are indexed by `source:id`, replaced by it, and deleted
+    /// with `"op": "delete"`.
+    Ingest {
         #[arg(long, short = 'i')]
-        index: Option<String>,
+        index: String,
+
+        /// File of JSON Lines, or '-' for stdin.
+        #[arg(default_value = "-")]
+        file: PathBuf,
+    },
+    /// Write the documents of an index as JSON Lines, which are read by `ingest`.
+    Export {
+        #[arg(long, short = 'i')]
+        index: String,
+
+        /// Export the documents without the body if the body field is not stored.
+        #[arg(long)]
+        allow_missing_body: bool,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    Search(SearchArgs),
+    /// Search the Markdown and text files (`.md` and `.txt`) under directories without
+    /// configuring an index, like grep.
+    Grep {
+        /// Tokenizer of the files, configured in the config file or built in tantivy, e.g.
+        /// 'default'.
+        #[arg(long, short = 't')]
+        tokenizer: Option<String>,
+
+        /// Keep the index under the data directory, and update it on the next search of the
+        /// directories.
+        #[arg(long)]
+        cache: bool,
+
+        /// Build the index in memory, even if the cache is enabled in the config file.
+        #[arg(long, conflicts_with = "cache")]
+        no_cache: bool,
+
+        #[arg(long, short = 'l')]
+        limit: Option<usize>,
+
+        /// Number of snippets to show for each document.
+        #[arg(long, short = 'n')]
+        snippets: Option<usize>,
+
+        /// Maximum number of characters of a snippet.
+        #[arg(long)]
+        snippet_length: Option<usize>,
+
+        #[arg(long, short = 'f', value_enum)]
+        format: Option<OutputFormat>,
+
+        /// Layout of each result, e.g. '{{path}}:{{title}}'. Implies `--format template`.
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Print `path:line:column:text` for each matching term. Same as `--format vimgrep`.
+        #[arg(long, conflicts_with = "format")]
+        vimgrep: bool,
+
+        /// Maximum number of matching lines printed for each file by `--vimgrep`. Words matched
+        /// by prefix or fuzzily are not printed.
+        #[arg(long, short = 'm', default_value_t = 10)]
+        max_count: usize,
+
+        query: String,
+
+        /// Directories to search, the current directory if none is given.
+        dirs: Vec<PathBuf>,
+    },
+    /// Find documents similar to a document.
+    Similar {
+        /// Indexes to search, can be given multiple times.
+        #[arg(long = "index", short = 'i')]
+        indexes: Vec<String>,
+
+        /// Search all indexes in the config file.
+        #[arg(long, conflicts_with = "indexes")]
+        all: bool,
 
         #[arg(long, short = 'l')]
         limit: Option<usize>,
 
-        query: Vec<String>,
+        /// Show the score of each result.
+        #[arg(long)]
+        show_score: bool,
+
+        #[arg(long, short = 'f', value_enum)]
+        format: Option<OutputFormat>,
+
+        /// Layout of each result, e.g. '{{path}}:{{title}}'. Implies `--format template`.
+        #[arg(long)]
+        template: Option<String>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        /// Path of the file or id of the document, e.g. 'notes:a.md'.
+        target: String,
+    },
+    /// Serve the indexes over HTTP with JSON responses.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8765")]
+        listen: String,
+    },
+    /// Serve the indexes to coding assistants over the Model Context Protocol on stdio.
+    Mcp,
+    /// Serve the indexes to editors over the Language Server Protocol on stdio.
+    Lsp,
+    /// Keep the indexes open and serve searches of the command line over a Unix socket.
+    #[cfg(unix)]
+    Daemon,
+    /// Search interactively in the terminal.
+    Tui {
+        /// Indexes to search, switched with Tab.
+        #[arg(long = "index", short = 'i')]
+        indexes: Vec<String>,
+
+        /// Search all indexes in the config file.
+        #[arg(long, conflicts_with = "indexes")]
+        all: bool,
+
+        #[arg(long, short = 'l')]
+        limit: Option<usize>,
     },
 }
 
+#[derive(Args, Debug)]
+struct SearchArgs {
+    /// Indexes to search, can be given multiple times.
+    #[arg(long = "index", short = 'i')]
+    indexes: Vec<String>,
+
+    /// Search all indexes in the config file.
+    #[arg(long, conflicts_with = "indexes")]
+    all: bool,
+
+    #[arg(long, short = 'l')]
+    limit: Option<usize>,
+
+    /// Number of results to skip.
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+
+    /// Print only the number of matching documents.
+    #[arg(long)]
+    count: bool,
+
+    /// Show the score of each result.
+    #[arg(long)]
+    show_score: bool,
+
+    /// Explain the parsed query and the score of each result.
+    #[arg(long)]
+    explain: bool,
+
+    /// Match terms within a small edit distance, as configured in the schema.
+    #[arg(long)]
+    fuzzy: bool,
+
+    /// Match documents containing all words of the query.
+    #[arg(long, conflicts_with = "or")]
+    and: bool,
+
+    /// Match documents containing any word of the query.
+    #[arg(long)]
+    or: bool,
+
+    /// Number of words to match with `--or`, e.g. '2', '-1' or '75%'. Implies `--or`.
+    #[arg(long, alias = "mm", allow_hyphen_values = true, conflicts_with = "and")]
+    minimum_should_match: Option<MinimumShouldMatch>,
+
+    /// Favor recently modified documents when sorted by relevance.
+    #[arg(long, conflicts_with = "no_recency")]
+    recency: bool,
+
+    /// Do not favor recently modified documents, even if enabled in the config file.
+    #[arg(long)]
+    no_recency: bool,
+
+    /// Time after which the recency boost of a document halves, e.g. '30d'. Implies
+    /// `--recency`.
+    #[arg(long, value_parser = parse_duration, conflicts_with = "no_recency")]
+    half_life: Option<TimeDelta>,
+
+    /// Weight of the recency between 0 and 1. Implies `--recency`.
+    #[arg(long, conflicts_with = "no_recency")]
+    recency_weight: Option<f32>,
+
+    /// Fail on invalid query syntax instead of searching it as literal text.
+    #[arg(long)]
+    strict: bool,
+
+    /// Number of snippets to show for each document.
+    #[arg(long, short = 'n')]
+    snippets: Option<usize>,
+
+    /// Maximum number of characters of a snippet.
+    #[arg(long)]
+    snippet_length: Option<usize>,
+
+    #[arg(long, short = 'f', value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Layout of each result, e.g. '{{path}}:{{title}}'. Implies `--format template`.
+    #[arg(long)]
+    template: Option<String>,
+
+    /// Print `path:line:column:text` for each matching term. Same as `--format vimgrep`.
+    #[arg(long, conflicts_with = "format")]
+    vimgrep: bool,
+
+    /// Maximum number of matching lines printed for each file by `--vimgrep`. Words matched
+    /// by prefix or fuzzily are not printed.
+    #[arg(long, short = 'm', default_value_t = 10)]
+    max_count: usize,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[arg(long, value_enum)]
+    sort: Option<Sort>,
+
+    /// Reverse the order of results.
+    #[arg(long, short = 'r')]
+    reverse: bool,
+
+    query: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// Search only documents from the source, can be given multiple times.
+    #[arg(long = "source", short = 's')]
+    sources: Vec<String>,
+
+    /// Search only documents whose path relative to the source starts with the prefix or
+    /// matches the glob pattern.
+    #[arg(long)]
+    path: Option<String>,
+
+    /// Search only documents modified since the time, e.g. '7d', '2w' or '2025-01-31'.
+    #[arg(long, value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+
+    /// Search only documents modified until the time, e.g. '7d', '2w' or '2025-01-31'.
+    #[arg(long, value_parser = parse_until)]
+    until: Option<DateTime<Utc>>,
+}
+
+impl From<&FilterArgs> for Filter {
+    fn from(value: &FilterArgs) -> Self {
+        Filter {
+            sources: value.sources.clone(),
+            path: value.path.clone(),
+            since: value.since,
+            until: value.until,
+        }
+    }
+}
+
 #[derive(Args, Debug)]
 #[group(required = false, multiple = false)]
 struct IndexMode {
@@ -62,22 +336,22 @@ fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
 
-    let config_path = cli.config.unwrap_or_else(|| {
-        get_default_config_path().unwrap_or_else(|e| {
-            error!("Failed to get the config file path, {e}");
-            process::exit(1);
-        })
-    });
-    let config = Config::load(config_path.as_path()).unwrap_or_else(|e| {
-        error!("Failed to load {:?}, {}.", config_path, e);
-        process::exit(1);
-    });
+    let config_path = match cli.config {
+        Some(x) => x,
+        None => get_default_config_path()
+            .map_err(|e| anyhow::anyhow!("Failed to get the config file path, {}", e))?,
+    };
+    let start_at = Instant::now();
+    let config = Config::load(config_path.as_path())
+        .map_err(|e| anyhow::anyhow!("Failed to load {:?}, {}.", config_path, e))?;
+    debug!("Loaded {:?} in {:?}.", config_path, start_at.elapsed());
 
     match &cli.command {
         Commands::Index {
             indexes,
             index_mode,
         } => {
+            let tokenizers = Tokenizers::new(config.tokenizers.clone());
             let mut indexer = Indexer::new();
 
             if index_mode.path.is_none() {
@@ -88,10 +362,7 @@ fn main() -> anyhow::Result<()> {
                 ) {
                     (true, _, _) => indexer.set_increment(false),
                     (_, true, true) => indexer.set_increment(true),
-                    (_, true, false) => {
-                        error!("Cannot execute incremental index.");
-                        process::exit(1);
-                    }
+                    (_, true, false) => anyhow::bail!("Cannot execute incremental index."),
                     (_, false, true) => indexer,
                     (_, false, false) => {
                         warn!("Fall back to full index.");
@@ -107,67 +378,391 @@ fn main() -> anyhow::Result<()> {
                 .try_for_each(|(index_name, index_config)| {
                     let schema_config = config.get_schema(index_config.schema.as_str())?;
                     let index_path = index_config.get_path(index_name)?;
-                    let index =
-                        &create_index(index_path, schema_config, config.tokenizers.clone())?;
+                    let index = &match index_mode.full {
+                        true => recreate_index(index_path, schema_config, &tokenizers)?,
+                        false => create_index(index_path, schema_config, &tokenizers)?,
+                    };
 
                     match index_mode.path.as_ref() {
-                        Some(p) => indexer.index_file(index, index_config.sources.clone(), p.clone()),
-                        None => indexer.index(index_name.to_string(), index, index_config.sources.clone()),
+                        Some(p) => {
+                            indexer.index_file(index, index_config.sources.clone(), p.clone())
+                        }
+                        None => indexer.index(index_name.to_string(), index, index_config),
                     }
                 })?;
 
             eprintln!("{} documents were indexed.", indexer.indexed_count());
         }
-        Commands::Search {
+        Commands::Ingest { index, file } => {
+            let tokenizers = Tokenizers::new(config.tokenizers.clone());
+            let index_config = config
+                .indexes
+                .get(index)
+                .ok_or_else(|| shunbin::Error::IndexNotFound(index.clone()))?;
+            let index = open_index(&config, &tokenizers, index.as_str())?;
+
+            let mut indexer = Indexer::new();
+            match file.as_os_str() == "-" {
+                true => indexer.ingest(&index, &index_config.sources, stdin().lock())?,
+                false => indexer.ingest(
+                    &index,
+                    &index_config.sources,
+                    BufReader::new(File::open(file)?),
+                )?,
+            }
+
+            eprintln!(
+                "{} documents were indexed, {} were deleted.",
+                indexer.indexed_count(),
+                indexer.deleted_count()
+            );
+        }
+        Commands::Export {
             index,
+            allow_missing_body,
+            filter,
+        } => {
+            let tokenizers = Tokenizers::new(config.tokenizers.clone());
+            let index = open_index(&config, &tokenizers, index.as_str())?;
+
+            let mut out = BufWriter::new(stdout().lock());
+            let result = export(&index, &Filter::from(filter), *allow_missing_body, &mut out)
+                .and_then(|x| out.flush().map(|_| x).map_err(shunbin::Error::from));
+            let count = match result {
+                Err(shunbin::Error::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => {
+                    return Ok(());
+                }
+                x => x?,
+            };
+            eprintln!("{} documents were exported.", count);
+        }
+        Commands::Search(args) => {
+            let is_tty = stdout().is_terminal();
+            #[cfg(unix)]
+            if !cli.no_daemon && daemon::forward_search(config_path.as_path(), is_tty)? {
+                return Ok(());
+            }
+
+            let workspace = Workspace::new(config);
+            let mut out = stdout().lock();
+            search(&workspace, args, &mut out, &mut stderr(), is_tty)?;
+        }
+        Commands::Grep {
+            tokenizer,
+            cache,
+            no_cache,
             limit,
+            snippets,
+            snippet_length,
+            format,
+            template,
+            vimgrep,
+            max_count,
             query,
+            dirs,
         } => {
-            // Determine the target index in the following order:
-            // 1. Command line argument
-            // 2. `default_opts.search.index` in the config file
-            // 3. If there is only one entry in `indexes` in the config file, use that
-            // 4. Error
-            let index_name = &index.clone().unwrap_or_else(|| {
-                config.get_default_search_index_name().unwrap_or_else(|e| {
-                    error!("Please specify the index to search, {}", e);
-                    process::exit(1);
-                })
-            });
+            let dirs = match dirs.is_empty() {
+                true => vec![PathBuf::from(".")],
+                false => dirs.clone(),
+            };
+            let tokenizer = tokenizer
+                .clone()
+                .or_else(|| config.get_default_grep_tokenizer())
+                .unwrap_or_else(|| "default".to_string());
+            let cache = !no_cache && (*cache || config.get_default_grep_cache());
+            let grep = Grep::new(dirs).set_tokenizer(tokenizer).set_cache(cache);
+            let index = grep.build(&Tokenizers::new(config.tokenizers.clone()))?;
+
+            let template = template
+                .clone()
+                .or_else(|| config.get_default_search_template());
+            let format = match vimgrep {
+                true => OutputFormat::Vimgrep,
+                false => resolve_format(&config, *format, template.as_ref()),
+            };
+            let mut grep_query = Query::new(query.as_str());
+            if let Some(x) = limit {
+                grep_query = grep_query.set_limit(*x);
+            }
+            if let Some(x) = snippets {
+                grep_query = grep_query.set_snippets(*x);
+            }
+            if let Some(x) = snippet_length {
+                grep_query = grep_query.set_snippet_length(*x);
+            }
+            let results = grep.search(&index, &grep_query, &config)?;
+            for warning in results.warnings.iter() {
+                eprintln!("{}", warning);
+            }
 
-            let index_config = config.indexes.get(index_name).unwrap_or_else(|| {
-                error!("Failed to get the index config named '{}'.", index_name);
-                process::exit(1);
-            });
-            let schema_config = config.get_schema(index_config.schema.as_str())?;
-            let index_path = index_config.get_path(index_name)?;
-            let index = &create_index(index_path, schema_config, config.tokenizers.clone())?;
+            let mut locators = HashMap::<String, Locator>::new();
+            if format == OutputFormat::Vimgrep {
+                let analyzer = index.tokenizer_for_field(index.schema().get_field("body")?)?;
+                let locator =
+                    Locator::new(analyzer, results.terms.clone()).set_max_count(*max_count);
+                locators.insert(GREP_INDEX_NAME.to_string(), locator);
+            }
+
+            let indexes = HashMap::from([(GREP_INDEX_NAME.to_string(), grep.index_config()?)]);
+            Printer::new(format)
+                .set_template(template)
+                .set_locators(locators)
+                .set_tty(stdout().is_terminal())
+                .print(&results, &indexes)?;
+        }
+        Commands::Similar {
+            indexes,
+            all,
+            limit,
+            show_score,
+            format,
+            template,
+            filter,
+            target,
+        } => {
+            let workspace = Workspace::new(config);
+            let index_names = resolve_index_names(&workspace, *all, indexes)?;
+            let config = workspace.config();
+            let template = template
+                .clone()
+                .or_else(|| config.get_default_search_template());
+            // Similar documents are not found by query terms, so there are no lines to locate.
+            let format = match resolve_format(config, *format, template.as_ref()) {
+                OutputFormat::Vimgrep => {
+                    warn!("Cannot locate lines of similar documents, print paths instead.");
+                    OutputFormat::Paths
+                }
+                x => x,
+            };
+
+            let (doc, body) = find_document(&workspace, &index_names, target)?;
+            debug!("Find documents similar to {}.", doc.id);
+
+            let mut similar_query = Query::new("")
+                .set_indexes(index_names.clone())
+                .set_filter(Filter::from(filter));
+            if let Some(x) = limit {
+                similar_query = similar_query.set_limit(*x);
+            }
+            let results = workspace.similar(&doc, body.as_str(), &similar_query)?;
 
+            Printer::new(format)
+                .set_template(template)
+                .set_show_index(index_names.len() > 1)
+                .set_show_score(*show_score)
+                .print(&results, &workspace.config().indexes)?;
+        }
+        Commands::Tui {
+            indexes,
+            all,
+            limit,
+        } => {
             let limit = limit.unwrap_or_else(|| config.get_default_search_limit());
-            let docs = search(index, query.join(" ").as_str(), limit)?;
-
-            docs.into_iter().try_for_each(|doc| -> anyhow::Result<()> {
-                let doc_path = match doc.absolute_path(&index_config.sources) {
-                    Ok(x) => x.to_string_lossy().to_string(),
-                    Err(e) => {
-                        error!("{}", e);
-                        return Ok(());
-                    }
-                };
+            let workspace = Workspace::new(config);
+            let index_names = resolve_index_names(&workspace, *all, indexes)?;
+            tui::run(workspace, index_names, limit)?;
+        }
+        Commands::Serve { listen } => {
+            Server::new(Workspace::new(config)).listen(listen.as_str())?;
+        }
+        #[cfg(unix)]
+        Commands::Daemon => {
+            Daemon::new(config_path.as_path(), config)?.listen()?;
+        }
+        Commands::Mcp => {
+            McpServer::new(Server::new(Workspace::new(config))).serve(stdin().lock(), stdout())?;
+        }
+        Commands::Lsp => {
+            LspServer::new(Server::new(Workspace::new(config))).serve(stdin().lock(), stdout())?;
+        }
+    };
 
-                debug!("{:?}", doc);
+    Ok(())
+}
 
-                println!(
-                    "{}, {}, {}",
-                    doc.title,
-                    doc.updated_at.to_rfc3339(),
-                    doc_path,
-                );
+/// Searches with the arguments of the `search` command, and prints the results to the writers.
+///
+/// `is_tty` tells whether the output goes to a terminal, to highlight matches with colors.
+fn search(
+    workspace: &Workspace,
+    args: &SearchArgs,
+    out: &mut impl Write,
+    err: &mut impl Write,
+    is_tty: bool,
+) -> anyhow::Result<()> {
+    let config = workspace.config();
+    let index_names = resolve_index_names(workspace, args.all, &args.indexes)?;
 
-                Ok(())
-            })?;
+    let template = args
+        .template
+        .clone()
+        .or_else(|| config.get_default_search_template());
+    let format = match args.vimgrep {
+        true => OutputFormat::Vimgrep,
+        false => resolve_format(config, args.format, template.as_ref()),
+    };
+    // Explanations are written only in the plain text and JSON formats.
+    let format = match format {
+        OutputFormat::Plain | OutputFormat::Json | OutputFormat::Jsonl => format,
+        _ if args.explain => {
+            writeln!(err, "Use the plain format to explain the query.")?;
+            OutputFormat::Plain
         }
+        _ => format,
     };
 
+    let mut query = Query::new(args.query.join(" "))
+        .set_indexes(index_names.clone())
+        .set_offset(args.offset)
+        .set_filter(Filter::from(&args.filter))
+        .set_reverse(args.reverse)
+        .set_fuzzy(args.fuzzy)
+        .set_strict(args.strict)
+        .set_explain(args.explain);
+    if let Some(x) = args.limit {
+        query = query.set_limit(x);
+    }
+    match (args.count, args.snippets) {
+        (true, _) => query = query.set_snippets(0),
+        (false, Some(x)) => query = query.set_snippets(x),
+        (false, None) => {}
+    }
+    if let Some(x) = args.snippet_length {
+        query = query.set_snippet_length(x);
+    }
+    if let Some(x) = args.sort {
+        query = query.set_sort(x);
+    }
+    if args.and {
+        query = query.set_operator(Operator::And);
+    } else if args.or {
+        query = query.set_operator(Operator::Or);
+    }
+    if let Some(x) = args.minimum_should_match {
+        query = query.set_minimum_should_match(x);
+    }
+    if args.no_recency {
+        query = query.set_recency(None);
+    } else if args.recency || args.half_life.is_some() || args.recency_weight.is_some() {
+        let mut x = config.get_default_search_recency()?;
+        if let Some(half_life) = args.half_life {
+            x = x.set_half_life(half_life);
+        }
+        if let Some(weight) = args.recency_weight {
+            x = x.set_weight(weight);
+        }
+        query = query.set_recency(Some(x));
+    }
+
+    let start_at = Instant::now();
+    let results = workspace.search(&query)?;
+    debug!("Searched {:?} in {:?}.", index_names, start_at.elapsed());
+    for warning in results.warnings.iter() {
+        writeln!(err, "{}", warning)?;
+    }
+
+    if args.count {
+        writeln!(out, "{}", results.count)?;
+        return Ok(());
+    }
+
+    results.docs.iter().for_each(|doc| debug!("{:?}", doc));
+
+    let mut locators = HashMap::<String, Locator>::new();
+    if format == OutputFormat::Vimgrep {
+        for index_name in index_names.iter() {
+            let locator = workspace
+                .locator(index_name, results.terms.clone())?
+                .set_max_count(args.max_count);
+            locators.insert(index_name.clone(), locator);
+        }
+    }
+
+    Printer::new(format)
+        .set_template(template)
+        .set_locators(locators)
+        .set_show_index(index_names.len() > 1)
+        .set_show_score(args.show_score)
+        .set_tty(is_tty)
+        .print_to(&results, &config.indexes, out, err)?;
     Ok(())
 }
+
+/// Returns all indexes with `--all`, or the indexes of the arguments or the default index.
+fn resolve_index_names(
+    workspace: &Workspace,
+    all: bool,
+    indexes: &[String],
+) -> Result<Vec<String>, shunbin::Error> {
+    match all {
+        true => Ok(workspace.all_index_names()),
+        false => workspace.index_names(indexes),
+    }
+}
+
+fn resolve_format(
+    config: &Config,
+    format: Option<OutputFormat>,
+    template: Option<&String>,
+) -> OutputFormat {
+    match (format, template) {
+        (Some(x), _) => x,
+        (None, Some(_)) => OutputFormat::Template,
+        (None, None) => config.get_default_search_format(),
+    }
+}
+
+/// Finds a document by the path of the file or the id, and returns it with the body.
+///
+/// The target indexes are looked up first, and then the other indexes. The body is read from
+/// the file if it is not stored.
+fn find_document(
+    workspace: &Workspace,
+    index_names: &[String],
+    target: &str,
+) -> anyhow::Result<(Doc, String)> {
+    let config = workspace.config();
+    let path = Path::new(target);
+    let ids = match path.exists() {
+        true => {
+            let path = std::path::absolute(path)?;
+            config
+                .indexes
+                .values()
+                .flat_map(|x| x.sources.iter())
+                .filter_map(|(source_name, source)| {
+                    let source = std::path::absolute(source).ok()?;
+                    let relative_path = path.strip_prefix(source).ok()?;
+                    Some(format!("{}:{}", source_name, relative_path.to_str()?))
+                })
+                .collect::<Vec<_>>()
+        }
+        false => vec![target.to_string()],
+    };
+
+    let mut names = index_names.to_vec();
+    names.extend(
+        config
+            .indexes
+            .keys()
+            .filter(|x| !index_names.contains(x))
+            .cloned(),
+    );
+    for id in ids.iter() {
+        let doc = match workspace.find_document(&names, id) {
+            Ok(x) => x,
+            Err(shunbin::Error::DocumentNotFound(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let body = match doc.body.clone() {
+            Some(x) => x,
+            None => {
+                let sources = &config.indexes[&doc.index].sources;
+                std::fs::read_to_string(doc.absolute_path(sources)?)?
+            }
+        };
+        return Ok((doc, body));
+    }
+
+    anyhow::bail!("Not found the document '{}' in the indexes.", target)
+}
@@ -0,0 +1,492 @@
+use std::io::{BufRead, Write};
+
+use log::{debug, error, warn};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::jsonrpc::{
+    INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR, RpcError, response,
+};
+use crate::output::to_json;
+use crate::serve::{RequestError, SearchRequest, Server};
+
+/// Protocol versions supported, newest first.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const INDEX_URI_PREFIX: &str = "shunbin://indexes/";
+
+/// The resource does not exist, as defined by MCP.
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+#[derive(Deserialize)]
+struct GetDocumentArgs {
+    id: String,
+    #[serde(default)]
+    index: Option<String>,
+}
+
+/// A Model Context Protocol server over stdio, exposing the search as tools and the indexes as
+/// resources.
+pub struct McpServer {
+    server: Server,
+}
+
+impl McpServer {
+    pub fn new(server: Server) -> Self {
+        Self { server }
+    }
+
+    /// Reads JSON-RPC messages, one per line, and writes the responses until the input is
+    /// closed.
+    pub fn serve(&self, reader: impl BufRead, mut writer: impl Write) -> anyhow::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Value>(line.as_str()) {
+                Ok(message) => self.handle(message),
+                Err(e) => Some(response(
+                    Value::Null,
+                    Err(RpcError::new(
+                        PARSE_ERROR,
+                        format!("Failed to parse the message, {}", e),
+                    )),
+                )),
+            };
+            if let Some(x) = response {
+                serde_json::to_writer(&mut writer, &x)?;
+                writeln!(writer)?;
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles a request or a notification, and returns the response to a request.
+    fn handle(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // Responses from the client are not expected, since no request is sent.
+            return id
+                .map(|x| response(x, Err(RpcError::new(INVALID_REQUEST, "Missing 'method'."))));
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let Some(id) = id else {
+            debug!("Received the notification {}.", method);
+            return None;
+        };
+        debug!("Received the request {} {}.", method, id);
+
+        let result = match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(tools()),
+            "tools/call" => self.call_tool(&params),
+            "resources/list" => Ok(self.list_resources()),
+            "resources/templates/list" => Ok(json!({ "resourceTemplates": [] })),
+            "resources/read" => self.read_resource(&params),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{}'.", method),
+            )),
+        };
+
+        Some(response(id, result))
+    }
+
+    fn initialize(&self, params: &Value) -> Value {
+        let requested = params.get("protocolVersion").and_then(Value::as_str);
+        let version = match requested {
+            Some(x) if PROTOCOL_VERSIONS.contains(&x) => x,
+            _ => PROTOCOL_VERSIONS[0],
+        };
+
+        json!({
+            "protocolVersion": version,
+            "capabilities": { "tools": {}, "resources": {} },
+            "serverInfo": { "name": "shunbin", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    fn call_tool(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing the tool name."))?;
+        let args = params.get("arguments").cloned().unwrap_or(json!({}));
+
+        let result = match name {
+            "search" => self.search(args),
+            "get_document" => self.get_document(args),
+            "list_indexes" => self.server.stats().and_then(|x| {
+                serde_json::to_string_pretty(&x).map_err(|e| RequestError::Internal(e.into()))
+            }),
+            _ => {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("Unknown tool '{}'.", name),
+                ));
+            }
+        };
+
+        // Errors of tools are returned as results, so the model can see and correct them.
+        Ok(match result {
+            Ok(text) => json!({
+                "content": [{ "type": "text", "text": text }],
+                "isError": false,
+            }),
+            Err(e) => {
+                if let RequestError::Internal(x) = &e {
+                    error!("{}", x);
+                }
+                json!({
+                    "content": [{ "type": "text", "text": e.to_string() }],
+                    "isError": true,
+                })
+            }
+        })
+    }
+
+    fn search(&self, args: Value) -> Result<String, RequestError> {
+        let request = serde_json::from_value::<SearchRequest>(args)
+            .map_err(|e| RequestError::BadRequest(format!("Invalid arguments, {}", e)))?;
+        let results = self.server.search(&request)?;
+        let value =
+            to_json(&results, &self.server.config().indexes).map_err(anyhow::Error::from)?;
+        Ok(serde_json::to_string_pretty(&value).map_err(anyhow::Error::from)?)
+    }
+
+    /// Returns the current contents of the file, which may be newer than the index.
+    fn get_document(&self, args: Value) -> Result<String, RequestError> {
+        let args = serde_json::from_value::<GetDocumentArgs>(args)
+            .map_err(|e| RequestError::BadRequest(format!("Invalid arguments, {}", e)))?;
+        // Ids are unique within an index, and search may return documents of any index.
+        let doc = self
+            .server
+            .find_document(args.index.as_slice(), args.id.as_str())?;
+
+        let sources = &self.server.config().indexes[&doc.index].sources;
+        let path = match doc.url.as_ref() {
+            // Documents ingested from JSON Lines have only the stored body.
+            Some(url) => {
+                return doc.body.ok_or_else(|| {
+                    RequestError::NotFound(format!("The body of {} is not stored.", url))
+                });
+            }
+            None => doc.absolute_path(sources)?,
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(x) => Ok(x),
+            Err(e) => {
+                warn!("Failed to read {:?}, {}", path, e);
+                doc.body.ok_or_else(|| {
+                    RequestError::NotFound(format!("Failed to read {:?}, {}", path, e))
+                })
+            }
+        }
+    }
+
+    fn list_resources(&self) -> Value {
+        let resources = self
+            .server
+            .config()
+            .indexes
+            .iter()
+            .map(|(index_name, index_config)| {
+                let sources = index_config
+                    .sources
+                    .iter()
+                    .map(|(name, path)| format!("{} ({})", name, path.to_string_lossy()))
+                    .collect::<Vec<_>>();
+                json!({
+                    "uri": format!("{}{}", INDEX_URI_PREFIX, index_name),
+                    "name": index_name,
+                    "description": format!("Index of {}", sources.join(", ")),
+                    "mimeType": "application/json",
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "resources": resources })
+    }
+
+    fn read_resource(&self, params: &Value) -> Result<Value, RpcError> {
+        let uri = params
+            .get("uri")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing 'uri'."))?;
+        let index_name = uri
+            .strip_prefix(INDEX_URI_PREFIX)
+            .filter(|x| self.server.config().indexes.contains_key(*x))
+            .ok_or_else(|| {
+                RpcError::new(
+                    RESOURCE_NOT_FOUND,
+                    format!("Not found the resource '{}'.", uri),
+                )
+            })?;
+        let stats = self
+            .server
+            .workspace()
+            .index_stats(index_name)
+            .map_err(anyhow::Error::from)?;
+
+        Ok(json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": "application/json",
+                "text": serde_json::to_string(&stats).map_err(anyhow::Error::from)?,
+            }],
+        }))
+    }
+}
+
+fn tools() -> Value {
+    let string_or_array = json!({
+        "anyOf": [
+            { "type": "string" },
+            { "type": "array", "items": { "type": "string" } },
+        ],
+    });
+
+    json!({
+        "tools": [
+            {
+                "name": "search",
+                "description": "Search the notes and documents with full text search. Returns the matching documents with their ids, paths and snippets.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Query such as 'auth design', '\"exact phrase\"', 'title:kubernetes' or 'kube*'",
+                        },
+                        "index": {
+                            "description": "Indexes to search, the default index if omitted",
+                            "anyOf": string_or_array["anyOf"],
+                        },
+                        "limit": { "type": "integer", "minimum": 1 },
+                        "offset": { "type": "integer", "minimum": 0 },
+                        "source": {
+                            "description": "Search only documents from the sources",
+                            "anyOf": string_or_array["anyOf"],
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "A prefix or a glob pattern of the path relative to the source",
+                        },
+                        "since": {
+                            "type": "string",
+                            "description": "Modified since a date such as '2025-01-31' or a duration before now such as '7d'",
+                        },
+                        "until": {
+                            "type": "string",
+                            "description": "Modified until a date such as '2025-01-31' or a duration before now such as '7d'",
+                        },
+                        "sort": {
+                            "type": "string",
+                            "enum": ["relevance", "modified", "indexed", "path", "title"],
+                        },
+                        "reverse": {
+                            "type": "boolean",
+                            "description": "Reverse the order of the sort",
+                        },
+                        "snippets": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Number of snippets of each document",
+                        },
+                        "snippet_length": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Maximum number of characters of a snippet",
+                        },
+                        "fuzzy": {
+                            "type": "boolean",
+                            "description": "Match misspelled words",
+                        },
+                        "strict": {
+                            "type": "boolean",
+                            "description": "Fail on syntax errors instead of ignoring the invalid part of the query",
+                        },
+                        "operator": {
+                            "type": "string",
+                            "enum": ["and", "or"],
+                            "description": "Whether documents must match all or any of the words",
+                        },
+                        "mm": {
+                            "type": "string",
+                            "description": "Minimum number of words to match with the 'or' operator, such as '2', '-1', '75%' or '-25%'",
+                        },
+                    },
+                    "required": ["query"],
+                },
+            },
+            {
+                "name": "get_document",
+                "description": "Get the contents of a document by the id returned by search.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string", "description": "Id such as 'notes:a.md'" },
+                        "index": { "type": "string" },
+                    },
+                    "required": ["id"],
+                },
+            },
+            {
+                "name": "list_indexes",
+                "description": "List the indexes with the number of documents and the source directories.",
+                "inputSchema": { "type": "object", "properties": {} },
+            },
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use shunbin::{Config, Indexer, Tokenizers, Workspace, create_ram_index};
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+[indexes.notes]
+schema = "default"
+sources = {}
+
+[schema.default]
+fields = { body = { tokenizer = "default", stored = true }, title = { tokenizer = "default" } }
+"#;
+
+    const DOCS: &str = r#"{"id":"1","source":"wiki","title":"Auth design","body":"The token flow of the service.","url":"https://example.com/1"}
+{"id":"2","source":"wiki","title":"Deploy","body":"Rolling upgrade of the cluster.","url":"https://example.com/2"}
+"#;
+
+    fn server() -> McpServer {
+        let config = toml::from_str::<Config>(CONFIG).unwrap();
+        let schema_config = config.get_schema("default").unwrap();
+        let index = create_ram_index(schema_config, &Tokenizers::new(HashMap::new())).unwrap();
+        Indexer::without_timestamps()
+            .ingest(&index, &HashMap::new(), DOCS.as_bytes())
+            .unwrap();
+
+        let workspace = Workspace::new(config);
+        workspace.set_index("notes", index).unwrap();
+        McpServer::new(Server::new(workspace))
+    }
+
+    /// Serves the messages, and returns the responses by their ids.
+    fn serve(messages: &[Value]) -> HashMap<i64, Value> {
+        let input = messages
+            .iter()
+            .map(|x| format!("{}\n", x))
+            .collect::<String>();
+        let mut output = Vec::<u8>::new();
+        server().serve(input.as_bytes(), &mut output).unwrap();
+
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|x| serde_json::from_str::<Value>(x).unwrap())
+            .map(|x| (x["id"].as_i64().unwrap(), x))
+            .collect()
+    }
+
+    fn call(id: i64, name: &str, arguments: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        })
+    }
+
+    fn text(response: &Value) -> &str {
+        response["result"]["content"][0]["text"].as_str().unwrap()
+    }
+
+    #[test]
+    fn serve_tools() {
+        let responses = serve(&[
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": { "protocolVersion": "2025-03-26" },
+            }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }),
+            call(3, "search", json!({ "query": "token" })),
+            call(4, "get_document", json!({ "id": "wiki:1" })),
+            call(5, "search", json!({ "query": "title:", "strict": true })),
+        ]);
+        assert_eq!(responses.len(), 5);
+
+        assert_eq!(responses[&1]["result"]["protocolVersion"], "2025-03-26");
+
+        let tools = responses[&2]["result"]["tools"].as_array().unwrap();
+        let names = tools.iter().map(|x| x["name"].as_str().unwrap());
+        assert_eq!(
+            names.collect::<Vec<_>>(),
+            vec!["search", "get_document", "list_indexes"]
+        );
+
+        assert_eq!(responses[&3]["result"]["isError"], false);
+        let results = serde_json::from_str::<Value>(text(&responses[&3])).unwrap();
+        assert_eq!(results["total"], 1);
+        assert_eq!(results["hits"][0]["url"], "https://example.com/1");
+
+        assert_eq!(text(&responses[&4]), "The token flow of the service.");
+
+        assert_eq!(responses[&5]["result"]["isError"], true);
+        assert!(text(&responses[&5]).starts_with("Failed to parse the query"));
+    }
+
+    #[test]
+    fn list_all_search_arguments() {
+        let args = json!({
+            "query": "auth",
+            "index": ["notes"],
+            "limit": 10,
+            "offset": 0,
+            "source": "docs",
+            "path": "design/",
+            "since": "7d",
+            "until": "2025-01-31",
+            "sort": "modified",
+            "reverse": true,
+            "snippets": 2,
+            "snippet_length": 80,
+            "fuzzy": true,
+            "strict": true,
+            "operator": "or",
+            "mm": "75%",
+        });
+        let tools = tools();
+        let properties = tools["tools"][0]["inputSchema"]["properties"]
+            .as_object()
+            .unwrap();
+        assert_eq!(
+            properties.keys().collect::<Vec<_>>(),
+            args.as_object().unwrap().keys().collect::<Vec<_>>()
+        );
+        assert!(serde_json::from_value::<SearchRequest>(args).is_ok());
+    }
+
+    #[test]
+    fn answer_errors() {
+        let responses = serve(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "unknown" }),
+            call(2, "unknown", json!({})),
+            call(3, "get_document", json!({ "id": "missing" })),
+        ]);
+        assert_eq!(responses[&1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[&2]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(responses[&3]["result"]["isError"], true);
+    }
+}
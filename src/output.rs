@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write, stderr, stdout};
+use std::path::Path;
+
+use log::error;
+use serde::Serialize;
+use shunbin::{
+    Doc, Error, IndexConfig, Locator, OutputFormat, QueryExplanation, SearchResults, Snippet,
+};
+
+const HIGHLIGHT_START: &str = "\x1b[1;31m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+const MARKER_START: &str = "**";
+const MARKER_END: &str = "**";
+
+/// A search result as written by structured formats.
+#[derive(Debug, Serialize)]
+struct Hit<'a> {
+    index: &'a str,
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f32>,
+    title: &'a str,
+    updated_at: String,
+    modified_at: String,
+    source: &'a str,
+    path: String,
+    relative_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    snippets: &'a [Snippet],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<&'a serde_json::Value>,
+}
+
+impl<'a> Hit<'a> {
+    /// Creates a hit with the URL or the absolute path of the document as the path.
+    fn new(doc: &'a Doc, path: String) -> Self {
+        Self {
+            index: doc.index.as_str(),
+            id: doc.id.as_str(),
+            score: doc.score,
+            title: doc.title.as_str(),
+            updated_at: doc.updated_at.to_rfc3339(),
+            modified_at: doc.modified_at.to_rfc3339(),
+            source: doc.source.as_str(),
+            path,
+            relative_path: doc.path.to_string_lossy().to_string(),
+            url: doc.url.as_deref(),
+            snippets: doc.snippets.as_slice(),
+            body: doc.body.as_deref(),
+            explanation: doc.explanation.as_ref(),
+        }
+    }
+
+    /// Returns the value of a field used by TSV, CSV and templates.
+    fn field(&self, name: &str, marker: (&str, &str)) -> Option<String> {
+        match name {
+            "index" => Some(self.index.to_string()),
+            "id" => Some(self.id.to_string()),
+            "score" => Some(self.score.map(|x| x.to_string()).unwrap_or_default()),
+            "title" => Some(self.title.to_string()),
+            "updated_at" => Some(self.updated_at.clone()),
+            "modified_at" => Some(self.modified_at.clone()),
+            "source" => Some(self.source.to_string()),
+            "path" => Some(self.path.clone()),
+            "relative_path" => Some(self.relative_path.clone()),
+            "url" => Some(self.url.unwrap_or_default().to_string()),
+            "snippet" => Some(
+                self.snippets
+                    .iter()
+                    .map(|x| flatten(x.highlight(marker.0, marker.1).as_str()))
+                    .collect::<Vec<_>>()
+                    .join(" ... "),
+            ),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Envelope<'a> {
+    total: usize,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    queries: &'a [QueryExplanation],
+    /// Problems in the query syntax.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    hits: Vec<Hit<'a>>,
+}
+
+impl<'a> Envelope<'a> {
+    fn new(results: &'a SearchResults, hits: Vec<Hit<'a>>) -> Self {
+        Self {
+            total: results.count,
+            queries: results.explanations.as_slice(),
+            warnings: results.warnings.iter().map(|x| x.to_string()).collect(),
+            hits,
+        }
+    }
+}
+
+pub struct Printer {
+    format: OutputFormat,
+    template: Option<String>,
+    locators: HashMap<String, Locator>,
+    show_index: bool,
+    show_score: bool,
+    is_tty: bool,
+}
+
+impl Printer {
+    const COLUMNS: [&str; 9] = [
+        "title",
+        "updated_at",
+        "modified_at",
+        "path",
+        "source",
+        "index",
+        "id",
+        "score",
+        "snippet",
+    ];
+
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            template: None,
+            locators: HashMap::new(),
+            show_index: false,
+            show_score: false,
+            is_tty: stdout().is_terminal(),
+        }
+    }
+
+    pub fn set_template(mut self, template: Option<String>) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Sets locators of matching terms for each index, used by the vimgrep format.
+    pub fn set_locators(mut self, locators: HashMap<String, Locator>) -> Self {
+        self.locators = locators;
+        self
+    }
+
+    /// Shows the index name of each document in the plain format.
+    pub fn set_show_index(mut self, show_index: bool) -> Self {
+        self.show_index = show_index;
+        self
+    }
+
+    /// Shows the score of each document in the plain format.
+    pub fn set_show_score(mut self, show_score: bool) -> Self {
+        self.show_score = show_score;
+        self
+    }
+
+    /// Highlights matches with colors instead of markers, by default if stdout is a terminal.
+    pub fn set_tty(mut self, is_tty: bool) -> Self {
+        self.is_tty = is_tty;
+        self
+    }
+
+    /// Prints the documents, and the number of matching documents to stderr or in the JSON
+    /// object.
+    pub fn print(
+        &self,
+        results: &SearchResults,
+        indexes: &HashMap<String, IndexConfig>,
+    ) -> io::Result<()> {
+        self.print_to(results, indexes, &mut stdout().lock(), &mut stderr())
+    }
+
+    /// Prints to the writers in place of stdout and stderr.
+    pub fn print_to(
+        &self,
+        results: &SearchResults,
+        indexes: &HashMap<String, IndexConfig>,
+        out: &mut impl Write,
+        err: &mut impl Write,
+    ) -> io::Result<()> {
+        let hits = to_hits(results, indexes);
+
+        if self.format != OutputFormat::Json {
+            writeln!(
+                err,
+                "{} of {} matching documents.",
+                hits.len(),
+                results.count
+            )?;
+        }
+
+        match self.write(out, err, hits, results) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            x => x,
+        }
+    }
+
+    fn write(
+        &self,
+        out: &mut impl Write,
+        err: &mut impl Write,
+        hits: Vec<Hit>,
+        results: &SearchResults,
+    ) -> io::Result<()> {
+        let marker = if self.is_tty {
+            (HIGHLIGHT_START, HIGHLIGHT_END)
+        } else {
+            (MARKER_START, MARKER_END)
+        };
+
+        match self.format {
+            OutputFormat::Plain => {
+                results
+                    .explanations
+                    .iter()
+                    .try_for_each(|x| write_query_explanation(out, x))?;
+                hits.iter().try_for_each(|hit| {
+                    let mut line = format!("{}, {}, {}", hit.title, hit.updated_at, hit.path);
+                    if self.show_index {
+                        line.push_str(format!(", {}", hit.index).as_str());
+                    }
+                    if self.show_score
+                        && let Some(score) = hit.score
+                    {
+                        line.push_str(format!(", {}", score).as_str());
+                    }
+                    writeln!(out, "{}", line)?;
+                    hit.snippets.iter().try_for_each(|x| {
+                        writeln!(
+                            out,
+                            "    {}",
+                            flatten(x.highlight(marker.0, marker.1).as_str())
+                        )
+                    })?;
+                    match hit.explanation {
+                        Some(x) => write_explanation(out, x, 1),
+                        None => Ok(()),
+                    }
+                })?
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, &Envelope::new(results, hits))?;
+                writeln!(out)?;
+            }
+            OutputFormat::Jsonl => hits.iter().try_for_each(|hit| {
+                serde_json::to_writer(&mut *out, hit)?;
+                writeln!(out)
+            })?,
+            OutputFormat::Tsv => {
+                writeln!(out, "{}", Self::COLUMNS.join("\t"))?;
+                hits.iter().try_for_each(|hit| {
+                    let row = Self::COLUMNS
+                        .iter()
+                        .map(|x| escape_tsv(hit.field(x, marker).unwrap_or_default().as_str()))
+                        .collect::<Vec<_>>();
+                    writeln!(out, "{}", row.join("\t"))
+                })?
+            }
+            OutputFormat::Csv => {
+                writeln!(out, "{}", Self::COLUMNS.join(","))?;
+                hits.iter().try_for_each(|hit| {
+                    let row = Self::COLUMNS
+                        .iter()
+                        .map(|x| escape_csv(hit.field(x, marker).unwrap_or_default().as_str()))
+                        .collect::<Vec<_>>();
+                    writeln!(out, "{}", row.join(","))
+                })?
+            }
+            OutputFormat::Paths => hits
+                .iter()
+                .try_for_each(|hit| writeln!(out, "{}", hit.path))?,
+            OutputFormat::Template => {
+                let template = self.template.as_deref().unwrap_or("{{path}}");
+                hits.iter()
+                    .try_for_each(|hit| writeln!(out, "{}", render(template, hit, marker)))?
+            }
+            OutputFormat::Vimgrep => hits.iter().try_for_each(|hit| {
+                // Only files have lines to locate.
+                let (Some(locator), None) = (self.locators.get(hit.index), hit.url) else {
+                    return Ok(());
+                };
+                let locations = match locator.locate_file(Path::new(hit.path.as_str())) {
+                    Ok(x) => x,
+                    Err(e) => return writeln!(err, "{}", e),
+                };
+                locations.iter().try_for_each(|x| {
+                    writeln!(out, "{}:{}:{}:{}", hit.path, x.line, x.column, x.text)
+                })
+            })?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the results as the JSON object of the JSON format, with the syntax warnings.
+pub fn to_json(
+    results: &SearchResults,
+    indexes: &HashMap<String, IndexConfig>,
+) -> serde_json::Result<serde_json::Value> {
+    serde_json::to_value(Envelope::new(results, to_hits(results, indexes)))
+}
+
+/// Returns the document as a JSON object of a hit, with the body if it is set.
+pub fn doc_to_json(doc: &Doc, index_config: &IndexConfig) -> Result<serde_json::Value, Error> {
+    let hit = Hit::new(doc, doc.location(&index_config.sources)?);
+    Ok(serde_json::to_value(hit)?)
+}
+
+fn to_hits<'a>(results: &'a SearchResults, indexes: &HashMap<String, IndexConfig>) -> Vec<Hit<'a>> {
+    results
+        .docs
+        .iter()
+        .filter_map(|doc| {
+            let Some(index_config) = indexes.get(doc.index.as_str()) else {
+                error!("Failed to get the index config named '{}'.", doc.index);
+                return None;
+            };
+            match doc.location(&index_config.sources) {
+                Ok(x) => Some(Hit::new(doc, x)),
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn write_query_explanation(out: &mut impl Write, x: &QueryExplanation) -> io::Result<()> {
+    writeln!(out, "Index: {}", x.index)?;
+    writeln!(out, "Query: {}", x.query)?;
+    writeln!(out, "Terms:")?;
+    x.terms.iter().try_for_each(|x| match x.phrase {
+        true => writeln!(out, "    {}: {} (phrase)", x.field, x.text),
+        false => writeln!(out, "    {}: {}", x.field, x.text),
+    })?;
+    writeln!(out)
+}
+
+/// Writes an explanation of the score as an indented tree.
+fn write_explanation(
+    out: &mut impl Write,
+    explanation: &serde_json::Value,
+    depth: usize,
+) -> io::Result<()> {
+    let indent = "    ".repeat(depth);
+    writeln!(
+        out,
+        "{}{:.4} {}",
+        indent,
+        explanation["value"].as_f64().unwrap_or_default(),
+        explanation["description"].as_str().unwrap_or_default()
+    )?;
+    if let Some(context) = explanation["context"].as_array() {
+        context
+            .iter()
+            .try_for_each(|x| writeln!(out, "{}  ({})", indent, x.as_str().unwrap_or_default()))?;
+    }
+    if let Some(details) = explanation["details"].as_array() {
+        details
+            .iter()
+            .try_for_each(|x| write_explanation(out, x, depth + 1))?;
+    }
+    Ok(())
+}
+
+/// Replaces `{{field}}` placeholders in the template with the fields of the hit.
+///
+/// Unknown placeholders are left as they are.
+fn render(template: &str, hit: &Hit, marker: (&str, &str)) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|x| start + x) else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        match hit.field(rest[start + 2..end].trim(), marker) {
+            Some(x) => rendered.push_str(x.as_str()),
+            None => rendered.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Joins the lines of a snippet into one line.
+fn flatten(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn escape_tsv(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::{Local, TimeZone};
+
+    use shunbin::SyntaxWarning;
+
+    use super::*;
+
+    fn doc() -> Doc {
+        let time = Local.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+        let mut doc = Doc::default();
+        doc.index = "notes".to_string();
+        doc.id = "a".to_string();
+        doc.score = Some(1.5);
+        doc.title = "Auth, \"design\"".to_string();
+        doc.updated_at = time;
+        doc.modified_at = time;
+        doc.source = "main".to_string();
+        doc.path = PathBuf::from("auth/design.md");
+        doc.snippets = vec![Snippet {
+            fragment: "the auth\nservice".to_string(),
+            highlighted: vec![4..8, 9..16],
+        }];
+        doc
+    }
+
+    #[test]
+    fn render_fields() {
+        let doc = doc();
+        let hit = Hit::new(&doc, "/notes/auth/design.md".to_string());
+        assert_eq!(
+            render(
+                "{{ title }} ({{index}}:{{relative_path}})",
+                &hit,
+                ("[", "]")
+            ),
+            "Auth, \"design\" (notes:auth/design.md)"
+        );
+        assert_eq!(
+            render("{{path}}: {{snippet}}", &hit, ("[", "]")),
+            "/notes/auth/design.md: the [auth] [service]"
+        );
+    }
+
+    #[test]
+    fn render_url_of_files_as_empty() {
+        let doc = doc();
+        let hit = Hit::new(&doc, "/notes/auth/design.md".to_string());
+        assert_eq!(render("<{{url}}>", &hit, ("", "")), "<>");
+    }
+
+    #[test]
+    fn leave_unknown_and_unclosed_placeholders() {
+        let doc = doc();
+        let hit = Hit::new(&doc, String::new());
+        assert_eq!(
+            render("{{unknown}} {{id}} {{title", &hit, ("", "")),
+            "{{unknown}} a {{title"
+        );
+    }
+
+    #[test]
+    fn escape_csv_fields() {
+        assert_eq!(escape_csv("auth"), "auth");
+        assert_eq!(escape_csv("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv("a\nb"), "\"a\nb\"");
+    }
+
+    #[test]
+    fn escape_tsv_fields() {
+        assert_eq!(escape_tsv("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    #[test]
+    fn write_headers_of_tsv_and_csv() {
+        let mut results = SearchResults::default();
+        results.docs = vec![doc()];
+        results.count = 1;
+        for (format, separator) in [(OutputFormat::Tsv, "\t"), (OutputFormat::Csv, ",")] {
+            let doc = doc();
+            let hits = vec![Hit::new(&doc, "/notes/auth/design.md".to_string())];
+            let mut out = Vec::<u8>::new();
+            Printer::new(format)
+                .write(&mut out, &mut Vec::new(), hits, &results)
+                .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.lines().count(), 2);
+            assert_eq!(
+                out.lines().next().unwrap(),
+                Printer::COLUMNS.join(separator)
+            );
+        }
+    }
+
+    #[test]
+    fn write_warnings_in_json() {
+        let mut results = SearchResults::default();
+        results.warnings = vec![SyntaxWarning {
+            query: "auth)".to_string(),
+            position: None,
+            message: "Unbalanced parenthesis".to_string(),
+        }];
+        let mut out = Vec::<u8>::new();
+        Printer::new(OutputFormat::Json)
+            .write(&mut out, &mut Vec::new(), Vec::new(), &results)
+            .unwrap();
+        let x = serde_json::from_slice::<serde_json::Value>(out.as_slice()).unwrap();
+        assert_eq!(
+            x["warnings"],
+            serde_json::json!(["Unbalanced parenthesis."])
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::filter::Filter;
+use crate::search::{MinimumShouldMatch, Operator, Recency, Sort};
+
+/// A search over one or more indexes, built with the `set_*` methods.
+///
+/// Options left unset fall back to `default_opts.search` in the config file when the query is
+/// run by `Workspace::search`.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    pub(crate) text: String,
+    pub(crate) indexes: Vec<String>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) offset: usize,
+    pub(crate) snippets: Option<usize>,
+    pub(crate) snippet_length: Option<usize>,
+    pub(crate) sort: Option<Sort>,
+    pub(crate) reverse: bool,
+    pub(crate) filter: Filter,
+    pub(crate) fuzzy: bool,
+    pub(crate) strict: bool,
+    pub(crate) explain: bool,
+    pub(crate) operator: Option<Operator>,
+    pub(crate) minimum_should_match: Option<MinimumShouldMatch>,
+    pub(crate) recency: Option<Option<Recency>>,
+}
+
+impl Query {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    /// Sets the indexes to search, the default index if empty.
+    pub fn set_indexes(mut self, indexes: Vec<String>) -> Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn set_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn set_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the number of snippets of each document, 0 for none.
+    pub fn set_snippets(mut self, snippets: usize) -> Self {
+        self.snippets = Some(snippets);
+        self
+    }
+
+    /// Sets the maximum number of characters of a snippet.
+    pub fn set_snippet_length(mut self, snippet_length: usize) -> Self {
+        self.snippet_length = Some(snippet_length);
+        self
+    }
+
+    pub fn set_sort(mut self, sort: Sort) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    /// Reverses the order of the sort.
+    pub fn set_reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    pub fn set_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Matches long query terms fuzzily, as configured in the schema of each index.
+    pub fn set_fuzzy(mut self, fuzzy: bool) -> Self {
+        self.fuzzy = fuzzy;
+        self
+    }
+
+    /// Fails on invalid query syntax instead of searching it as literal text.
+    pub fn set_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Explains the parsed query and the score of each document.
+    pub fn set_explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Sets the default operator. `Operator::Or` is implied by the minimum number of words to
+    /// match if the operator is not set.
+    pub fn set_operator(mut self, operator: Operator) -> Self {
+        self.operator = Some(operator);
+        self
+    }
+
+    pub fn set_minimum_should_match(mut self, minimum_should_match: MinimumShouldMatch) -> Self {
+        self.minimum_should_match = Some(minimum_should_match);
+        self
+    }
+
+    /// Favors recently modified documents when sorted by relevance, or not with `None`.
+    pub fn set_recency(mut self, recency: Option<Recency>) -> Self {
+        self.recency = Some(recency);
+        self
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What to do with the document of a record.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    /// Add the document, replacing the document of the same id.
+    #[default]
+    Upsert,
+    /// Delete the document of the id.
+    Delete,
+}
+
+impl Op {
+    fn is_upsert(&self) -> bool {
+        *self == Op::Upsert
+    }
+}
+
+/// A document as a line of JSON Lines.
+///
+/// Keys other than the fields of the schema are kept as custom fields, which are stored and
+/// searched as `fields.<key>:<value>`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Record {
+    #[serde(default, skip_serializing_if = "Op::is_upsert")]
+    pub op: Op,
+    /// Id unique within the source, indexed as `source:id`.
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Name of the source, which is set to the name of the command for records printed by it.
+    #[serde(default)]
+    pub source: String,
+    /// Path relative to the source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// URL of a document which is not a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
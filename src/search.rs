@@ -1,98 +1,1120 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use chrono::Local;
-use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
-use tantivy::schema::Value;
-use tantivy::{ReloadPolicy, TantivyDocument};
+use chrono::{Local, TimeDelta, Utc};
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Count, TopDocs};
+use tantivy::query::{
+    AllQuery, BooleanQuery, ConstScoreQuery, MoreLikeThisQuery, Occur, Query, QueryParser,
+    TermQuery,
+};
+use tantivy::query_grammar::{self, UserInputAst};
+use tantivy::schema::{FieldType, IndexRecordOption, OwnedValue, Schema, Type, Value};
+use tantivy::snippet::{SnippetGenerator, collapse_overlapped_ranges};
+use tantivy::{
+    DocId, IndexReader, Order, ReloadPolicy, Score, Searcher, SegmentReader, TantivyDocument, Term,
+};
 
-#[derive(Debug)]
+use crate::error::Error;
+use crate::filter::Filter;
+use crate::fuzzy::{Fuzzy, TermRewriter, split_prefixes};
+use crate::synonym::Synonyms;
+use crate::syntax::{SyntaxWarning, repair};
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
 pub struct Doc {
+    /// Name of the index the document was found in.
+    pub index: String,
+    pub id: String,
+    /// Relevance score, which is not computed for the results sorted by a field.
+    pub score: Option<f32>,
     pub title: String,
     pub updated_at: chrono::DateTime<Local>,
+    pub modified_at: chrono::DateTime<Local>,
     pub source: String,
     pub path: PathBuf,
+    /// URL of a document ingested from JSON Lines, which is not a file.
+    pub url: Option<String>,
+    /// The stored body, set only by `Workspace::find_document`.
+    pub body: Option<String>,
+    pub snippets: Vec<Snippet>,
+    /// Breakdown of the score, set with `SearchOptions::set_explain`.
+    pub explanation: Option<serde_json::Value>,
 }
 
 impl Doc {
-    pub fn absolute_path(&self, sources: &HashMap<String, PathBuf>) -> anyhow::Result<PathBuf> {
+    pub fn absolute_path(&self, sources: &HashMap<String, PathBuf>) -> Result<PathBuf, Error> {
+        if let Some(url) = self.url.as_ref() {
+            return Err(Error::NotFile {
+                id: self.id.clone(),
+                url: url.clone(),
+            });
+        }
         sources
             .get(self.source.as_str())
             .map(|x| PathBuf::from(x).join(self.path.clone()))
-            .ok_or_else(|| {
-                anyhow::anyhow!(
-                    "Failed to get the absolute path from source '{}' and path '{}'.",
-                    self.source,
-                    self.path.to_string_lossy()
-                )
+            .ok_or_else(|| Error::SourceNotFound {
+                source_name: self.source.clone(),
+                path: self.path.to_string_lossy().to_string(),
             })
     }
+
+    /// Returns the URL of the document, or the absolute path of the file.
+    pub fn location(&self, sources: &HashMap<String, PathBuf>) -> Result<String, Error> {
+        match self.url.as_ref() {
+            Some(x) => Ok(x.clone()),
+            None => Ok(self.absolute_path(sources)?.to_string_lossy().to_string()),
+        }
+    }
+}
+
+/// A fragment of the body with the ranges matching the query.
+#[derive(Debug, Serialize)]
+pub struct Snippet {
+    pub fragment: String,
+    pub highlighted: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// Returns the fragment with each match surrounded by `prefix` and `postfix`.
+    pub fn highlight(&self, prefix: &str, postfix: &str) -> String {
+        let mut text = String::new();
+        let mut start_from = 0;
+
+        for range in collapse_overlapped_ranges(&self.highlighted) {
+            text.push_str(&self.fragment[start_from..range.start]);
+            text.push_str(prefix);
+            text.push_str(&self.fragment[range.clone()]);
+            text.push_str(postfix);
+            start_from = range.end;
+        }
+        text.push_str(&self.fragment[start_from..]);
+        text
+    }
+}
+
+/// How a query was parsed and analyzed by an index.
+#[derive(Debug, Serialize)]
+pub struct QueryExplanation {
+    pub index: String,
+    /// The query text given to the query parser.
+    pub query: String,
+    pub terms: Vec<QueryTerm>,
+}
+
+/// A term looked up by a query, as produced by the tokenizer of its field.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct QueryTerm {
+    pub field: String,
+    pub text: String,
+    /// Whether the positions of the term are matched by a phrase.
+    pub phrase: bool,
+}
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct SearchResults {
+    pub docs: Vec<Doc>,
+    /// Number of all documents matching the query, regardless of the limit and the offset.
+    pub count: usize,
+    /// Texts of the query terms for the body field.
+    pub terms: Vec<String>,
+    pub explanations: Vec<QueryExplanation>,
+    pub warnings: Vec<SyntaxWarning>,
+}
+
+impl SearchResults {
+    /// Sets the name of the searched index to the documents.
+    pub fn set_index_name(mut self, index_name: &str) -> Self {
+        self.docs
+            .iter_mut()
+            .for_each(|x| x.index = index_name.to_string());
+        self.explanations
+            .iter_mut()
+            .for_each(|x| x.index = index_name.to_string());
+        self
+    }
+
+    /// Merges the results of multiple indexes into one ranking.
+    ///
+    /// BM25 scores are not comparable between indexes, so each score is normalized by the best
+    /// score of its index before merging. Each result should contain `offset + limit` documents.
+    pub fn merge(
+        results: Vec<SearchResults>,
+        offset: usize,
+        limit: usize,
+        sort: Sort,
+        reverse: bool,
+    ) -> SearchResults {
+        let mut terms = BTreeSet::<String>::new();
+        let mut docs = Vec::<(f32, Doc)>::new();
+        let mut count = 0;
+        let mut explanations = Vec::<QueryExplanation>::new();
+        let mut warnings = Vec::<SyntaxWarning>::new();
+
+        for x in results {
+            terms.extend(x.terms);
+            explanations.extend(x.explanations);
+            for warning in x.warnings {
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+            count += x.count;
+            let max_score = x.docs.iter().filter_map(|x| x.score).fold(0.0, f32::max);
+            docs.extend(x.docs.into_iter().map(|doc| {
+                let normalized = match doc.score {
+                    Some(score) if max_score > 0.0 => score / max_score,
+                    _ => 0.0,
+                };
+                (normalized, doc)
+            }));
+        }
+
+        match sort {
+            Sort::Relevance => docs.sort_by(|a, b| b.0.total_cmp(&a.0)),
+            _ if reverse => docs.sort_by(|a, b| sort.compare(&b.1, &a.1)),
+            _ => docs.sort_by(|a, b| sort.compare(&a.1, &b.1)),
+        }
+        let mut docs = docs
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(_, x)| x)
+            .collect::<Vec<_>>();
+        if reverse && sort == Sort::Relevance {
+            docs.reverse();
+        }
+
+        SearchResults {
+            docs,
+            count,
+            terms: terms.into_iter().collect(),
+            explanations,
+            warnings,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    /// By relevance to the query
+    #[default]
+    Relevance,
+    /// By the modification time of the file, newest first
+    Modified,
+    /// By the time indexed, newest first
+    Indexed,
+    /// By the path relative to the source
+    Path,
+    /// By the title
+    Title,
+}
+
+impl Sort {
+    /// Compares documents by the field, in the order used without `--reverse`.
+    fn compare(&self, a: &Doc, b: &Doc) -> Ordering {
+        match self {
+            Sort::Relevance => b
+                .score
+                .unwrap_or_default()
+                .total_cmp(&a.score.unwrap_or_default()),
+            Sort::Modified => b.modified_at.cmp(&a.modified_at),
+            Sort::Indexed => b.updated_at.cmp(&a.updated_at),
+            Sort::Path => a.path.cmp(&b.path),
+            Sort::Title => a.title.cmp(&b.title),
+        }
+    }
+
+    fn field_name(&self) -> &'static str {
+        match self {
+            Sort::Relevance => "",
+            Sort::Modified => "modified_at",
+            Sort::Indexed => "updated_at",
+            Sort::Path => "path",
+            Sort::Title => "title",
+        }
+    }
+
+    fn order(&self, reverse: bool) -> Order {
+        match (self, reverse) {
+            (Sort::Path | Sort::Title, false)
+            | (Sort::Relevance | Sort::Modified | Sort::Indexed, true) => Order::Asc,
+            _ => Order::Desc,
+        }
+    }
+}
+
+/// How the words of a query are combined when no operator is given.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Operator {
+    /// Documents must match all words
+    #[default]
+    And,
+    /// Documents must match any of the words
+    Or,
+}
+
+/// Number of optional words a document must match in the disjunctive mode, such as `2`, `-1`,
+/// `75%` and `-25%`.
+///
+/// A negative value is the number or the percentage of words allowed to be missing.
+/// A percentage is rounded up, so short queries stay as precise as in the conjunctive mode.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum MinimumShouldMatch {
+    Count(i64),
+    Percentage(i64),
+}
+
+impl MinimumShouldMatch {
+    /// Returns the number of clauses required to match out of `n` optional clauses.
+    fn resolve(&self, n: usize) -> usize {
+        let n = n as i64;
+        let required = match *self {
+            MinimumShouldMatch::Count(x) if x < 0 => n + x,
+            MinimumShouldMatch::Count(x) => x,
+            MinimumShouldMatch::Percentage(x) if x < 0 => n - n * -x / 100,
+            MinimumShouldMatch::Percentage(x) => (n * x + 99) / 100,
+        };
+        required.clamp(1, n.max(1)) as usize
+    }
+}
+
+impl FromStr for MinimumShouldMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid minimum should match '{}', expected such as '2', '-1' or '75%'",
+                s
+            )
+        };
+        match s.strip_suffix('%') {
+            Some(x) => x
+                .parse::<i64>()
+                .ok()
+                .filter(|x| (-100..=100).contains(x))
+                .map(MinimumShouldMatch::Percentage)
+                .ok_or_else(invalid),
+            None => s
+                .parse::<i64>()
+                .map(MinimumShouldMatch::Count)
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for MinimumShouldMatch {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Scoring which favors recently modified documents.
+///
+/// The score of a document is multiplied by `1 - weight + weight * 0.5^(age / half_life)`, where
+/// `age` is the time since the file was modified. A document modified one half-life ago loses
+/// half of `weight`.
+#[derive(Clone, Copy, Debug)]
+pub struct Recency {
+    half_life: TimeDelta,
+    weight: f32,
+}
+
+impl Default for Recency {
+    fn default() -> Self {
+        Self {
+            half_life: TimeDelta::days(30),
+            weight: 0.5,
+        }
+    }
+}
+
+impl Recency {
+    pub fn set_half_life(mut self, half_life: TimeDelta) -> Self {
+        self.half_life = half_life;
+        self
+    }
+
+    /// Sets the weight of the decay between 0, which ignores the time, and 1.
+    pub fn set_weight(mut self, weight: f32) -> Self {
+        self.weight = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns the factor of the score of a document modified at the time.
+    fn decay(&self, modified_at: i64, now: i64) -> Score {
+        let half_life = self.half_life.num_seconds().max(1) as f64;
+        let age = (now - modified_at).max(0) as f64;
+        let decay = 0.5_f64.powf(age / half_life) as Score;
+        1.0 - self.weight + self.weight * decay
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SnippetOptions {
+    pub max_chars: usize,
+    pub count: usize,
+}
+
+#[derive(Debug)]
+pub struct SearchOptions {
+    limit: usize,
+    offset: usize,
+    synonyms: Option<Synonyms>,
+    fuzzy: Option<Fuzzy>,
+    snippet: Option<SnippetOptions>,
+    filter: Filter,
+    sort: Sort,
+    reverse: bool,
+    explain: bool,
+    strict: bool,
+    operator: Operator,
+    minimum_should_match: Option<MinimumShouldMatch>,
+    recency: Option<Recency>,
+}
+
+impl SearchOptions {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            offset: 0,
+            synonyms: None,
+            fuzzy: None,
+            snippet: None,
+            filter: Filter::default(),
+            sort: Sort::default(),
+            reverse: false,
+            explain: false,
+            strict: false,
+            operator: Operator::default(),
+            minimum_should_match: None,
+            recency: None,
+        }
+    }
+
+    pub fn set_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn set_synonyms(mut self, synonyms: Option<Synonyms>) -> Self {
+        self.synonyms = synonyms;
+        self
+    }
+
+    /// Matches long query terms fuzzily.
+    pub fn set_fuzzy(mut self, fuzzy: Option<Fuzzy>) -> Self {
+        self.fuzzy = fuzzy;
+        self
+    }
+
+    pub fn set_snippet(mut self, snippet: Option<SnippetOptions>) -> Self {
+        self.snippet = snippet;
+        self
+    }
+
+    pub fn set_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn set_sort(mut self, sort: Sort, reverse: bool) -> Self {
+        self.sort = sort;
+        self.reverse = reverse;
+        self
+    }
+
+    /// Explains the parsed query and the score of each document.
+    pub fn set_explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Fails on invalid query syntax instead of searching it as literal text.
+    pub fn set_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets the default operator, and the minimum number of words to match with `Operator::Or`.
+    pub fn set_operator(
+        mut self,
+        operator: Operator,
+        minimum_should_match: Option<MinimumShouldMatch>,
+    ) -> Self {
+        self.operator = operator;
+        self.minimum_should_match = minimum_should_match;
+        self
+    }
+
+    /// Favors recently modified documents when sorted by relevance.
+    pub fn set_recency(mut self, recency: Option<Recency>) -> Self {
+        self.recency = recency;
+        self
+    }
 }
 
 pub fn search(
     index: &tantivy::index::Index,
     query: &str,
-    limit: usize,
-) -> anyhow::Result<Vec<Doc>> {
-    let reader = index
-        .reader_builder()
-        .reload_policy(ReloadPolicy::OnCommitWithDelay)
-        .try_into()?;
-    let searcher = reader.searcher();
+    options: &SearchOptions,
+) -> Result<SearchResults, Error> {
+    search_with(index, &open_searcher(index)?, query, options)
+}
 
+/// Searches with the searcher of a reader kept open, to search repeatedly without reopening.
+pub fn search_with(
+    index: &tantivy::index::Index,
+    searcher: &Searcher,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<SearchResults, Error> {
     let schema = index.schema();
     let field_title = schema.get_field("title")?;
     let field_body = schema.get_field("body")?;
-    let field_source = schema.get_field("source")?;
-    let field_path = schema.get_field("path")?;
     let field_updated_at = schema.get_field("updated_at")?;
 
     let query_parser = {
         let mut query_parser =
             QueryParser::for_index(index, vec![field_title, field_body, field_updated_at]);
-        query_parser.set_conjunction_by_default();
+        if options.operator == Operator::And {
+            query_parser.set_conjunction_by_default();
+        }
         query_parser
     };
-    let query = query_parser.parse_query(query)?;
+    let query_text = query;
+    let mut warnings = Vec::<SyntaxWarning>::new();
+    let query = if query.trim().is_empty() {
+        Box::new(AllQuery)
+    } else {
+        let query = match options.strict {
+            true => query.to_string(),
+            false => {
+                let (fields, json_fields) = field_names(&schema);
+                let (repaired, x) = repair(query, &fields, &json_fields);
+                if !x.is_empty() {
+                    debug!("Repaired the query to '{}'.", repaired);
+                }
+                warnings.extend(x.into_iter().map(|x| SyntaxWarning {
+                    message: format!("{}, searched as text", x.message),
+                    ..x
+                }));
+                repaired
+            }
+        };
+        let query = match options.synonyms.as_ref() {
+            Some(x) => {
+                let mut analyzer = index.tokenizer_for_field(field_body)?;
+                let expanded = x.expand(query.as_str(), &mut analyzer);
+                debug!("Expanded the query to '{}'.", expanded);
+                expanded
+            }
+            None => query,
+        };
+        let (stripped, has_prefixes) = split_prefixes(query.as_str());
+        let parsed = match query_parser.parse_query(stripped.as_str()) {
+            Ok(x) => x,
+            Err(e) if options.strict => {
+                let (fields, json_fields) = field_names(&schema);
+                let (_, x) = repair(query_text, &fields, &json_fields);
+                let details = x.iter().map(|x| format!("\n{}", x)).collect::<String>();
+                return Err(Error::InvalidQuery(format!("{}{}", e, details)));
+            }
+            Err(_) => {
+                let (parsed, errors) = query_parser.parse_query_lenient(stripped.as_str());
+                warnings.extend(errors.into_iter().map(|e| SyntaxWarning {
+                    query: query_text.to_string(),
+                    position: None,
+                    message: format!("{}, ignored the part of the query", e),
+                }));
+                parsed
+            }
+        };
+        let rewriter = TermRewriter::new(index, options.fuzzy.as_ref())?;
+        let default_occur = match options.operator {
+            Operator::And => Occur::Must,
+            Operator::Or => Occur::Should,
+        };
+        // Built again from the query with `*`, to match by prefix only the words with `*`.
+        let build = |ast| rewriter.build(&query_parser, ast, default_occur);
+        let required = match (options.operator, options.minimum_should_match) {
+            (Operator::Or, Some(x)) => require_words(query.as_str(), x, build),
+            _ => None,
+        };
+        match (required, has_prefixes) {
+            (Some(x), _) => x,
+            (None, false) => rewriter.rewrite(parsed),
+            (None, true) => build(query_grammar::parse_query_lenient(query.as_str()).0),
+        }
+    };
+
+    let mut results = collect(index, searcher, query, query_text, options)?;
+    warnings.append(&mut results.warnings);
+    results.warnings = warnings;
+    Ok(results)
+}
+
+/// Searches documents similar to the document, excluding the document itself.
+///
+/// Terms are taken from the title and `body`, so the body does not have to be stored.
+pub fn similar(
+    index: &tantivy::index::Index,
+    doc: &Doc,
+    body: &str,
+    options: &SearchOptions,
+) -> Result<SearchResults, Error> {
+    let searcher = open_searcher(index)?;
+
+    let schema = index.schema();
+    let field_title = schema.get_field("title")?;
+    let field_body = schema.get_field("body")?;
+    let field_id = schema.get_field("id")?;
+
+    let more_like_this: Box<dyn Query> = Box::new(
+        MoreLikeThisQuery::builder()
+            // A term only in the document itself does not relate it to others.
+            .with_min_doc_frequency(2)
+            .with_min_term_frequency(1)
+            .with_max_query_terms(50)
+            .with_document_fields(vec![
+                (field_title, vec![OwnedValue::from(doc.title.as_str())]),
+                (field_body, vec![OwnedValue::from(body)]),
+            ]),
+    );
+    let itself: Box<dyn Query> = Box::new(TermQuery::new(
+        Term::from_field_text(field_id, doc.id.as_str()),
+        IndexRecordOption::Basic,
+    ));
+    let query = Box::new(BooleanQuery::new(vec![
+        (Occur::Must, more_like_this),
+        (Occur::MustNot, itself),
+    ]));
+
+    collect(index, &searcher, query, "", options)
+}
+
+/// Finds a document by the id with a searcher of a reader kept open, with the body if it is
+/// stored.
+pub fn get_document_with(
+    index: &tantivy::index::Index,
+    searcher: &Searcher,
+    id: &str,
+) -> Result<Option<Doc>, Error> {
+    let schema = index.schema();
+    let field_body = schema.get_field("body")?;
+    let field_id = schema.get_field("id")?;
+
+    let query = TermQuery::new(
+        Term::from_field_text(field_id, id),
+        IndexRecordOption::Basic,
+    );
+    let Some((_, doc_address)) = searcher.search(&query, &TopDocs::with_limit(1))?.pop() else {
+        return Ok(None);
+    };
+    let doc: TantivyDocument = searcher.doc(doc_address)?;
+    let mut x = read_doc(&schema, &doc)?;
+    x.body = doc
+        .get_first(field_body)
+        .and_then(|x| x.as_str().map(String::from));
+    Ok(Some(x))
+}
+
+/// Opens a reader which reloads shortly after the index is committed.
+pub fn open_reader(index: &tantivy::index::Index) -> Result<IndexReader, Error> {
+    Ok(index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::OnCommitWithDelay)
+        .try_into()?)
+}
+
+fn open_searcher(index: &tantivy::index::Index) -> Result<Searcher, Error> {
+    Ok(open_reader(index)?.searcher())
+}
+
+/// Runs the query with the filters, and collects the documents in the order of the options.
+fn collect(
+    index: &tantivy::index::Index,
+    searcher: &Searcher,
+    query: Box<dyn Query>,
+    query_text: &str,
+    options: &SearchOptions,
+) -> Result<SearchResults, Error> {
+    let schema = index.schema();
+    let field_body = schema.get_field("body")?;
+
+    // Filters only narrow down documents and do not affect scores.
+    let query: Box<dyn Query> = if options.filter.is_empty() {
+        query
+    } else {
+        let mut subqueries = vec![(Occur::Must, query)];
+        options
+            .filter
+            .to_queries(&schema)?
+            .into_iter()
+            .for_each(|x| {
+                let filter: Box<dyn Query> = Box::new(ConstScoreQuery::new(x, 0.0));
+                subqueries.push((Occur::Must, filter));
+            });
+        Box::new(BooleanQuery::new(subqueries))
+    };
 
-    let top_docs = searcher.search(&query, &TopDocs::with_limit(limit))?;
+    let mut warnings = Vec::<SyntaxWarning>::new();
+    let snippet_generator = match options.snippet {
+        Some(_) if !schema.get_field_entry(field_body).is_stored() => {
+            warnings.push(SyntaxWarning {
+                query: query_text.to_string(),
+                position: None,
+                message: "Cannot create snippets, the body field is not stored".to_string(),
+            });
+            None
+        }
+        Some(snippet_options) => {
+            let mut generator = SnippetGenerator::create(searcher, query.as_ref(), field_body)?;
+            generator.set_max_num_chars(snippet_options.max_chars);
+            Some((generator, snippet_options.count))
+        }
+        None => None,
+    };
 
-    top_docs
+    let explanations = if options.explain {
+        vec![explain_query(&schema, query.as_ref(), query_text)]
+    } else {
+        Vec::new()
+    };
+
+    let mut terms = BTreeSet::<String>::new();
+    query.query_terms(&mut |term, _| {
+        if term.field() == field_body
+            && let Some(x) = term.value().as_str()
+        {
+            terms.insert(x.to_string());
+        }
+    });
+
+    let now = Utc::now().timestamp();
+    let collector = TopDocs::with_limit(options.limit).and_offset(options.offset);
+    let order = options.sort.order(options.reverse);
+    let (count, top_docs) = match options.sort {
+        Sort::Relevance => {
+            let (count, mut top_docs) = match options.recency {
+                Some(recency) => {
+                    let collector = collector.tweak_score(move |segment_reader: &SegmentReader| {
+                        let column = segment_reader.fast_fields().date("modified_at").ok();
+                        move |doc: DocId, score: Score| match column
+                            .as_ref()
+                            .and_then(|x| x.first(doc))
+                        {
+                            Some(x) => score * recency.decay(x.into_timestamp_secs(), now),
+                            None => score,
+                        }
+                    });
+                    searcher.search(&query, &(Count, collector))?
+                }
+                None => searcher.search(&query, &(Count, collector))?,
+            };
+            if options.reverse {
+                top_docs.reverse();
+            }
+            let top_docs = top_docs
+                .into_iter()
+                .map(|(score, x)| (Some(score), x))
+                .collect::<Vec<_>>();
+            (count, top_docs)
+        }
+        Sort::Modified | Sort::Indexed => {
+            let (count, top_docs) = searcher.search(
+                &query,
+                &(
+                    Count,
+                    collector
+                        .order_by_fast_field::<tantivy::DateTime>(options.sort.field_name(), order),
+                ),
+            )?;
+            (
+                count,
+                top_docs.into_iter().map(|(_, x)| (None, x)).collect(),
+            )
+        }
+        Sort::Path | Sort::Title => {
+            let (count, top_docs) = searcher.search(
+                &query,
+                &(
+                    Count,
+                    collector.order_by_string_fast_field(options.sort.field_name(), order),
+                ),
+            )?;
+            (
+                count,
+                top_docs.into_iter().map(|(_, x)| (None, x)).collect(),
+            )
+        }
+    };
+
+    let docs = top_docs
         .into_iter()
-        .map(|(_, doc_address)| {
-            searcher
-                .doc(doc_address)
-                .map(|doc: TantivyDocument| {
-                    let title = doc
-                        .get_first(field_title)
-                        .and_then(|x| x.as_str().map(String::from))
-                        .unwrap_or_default();
-                    let path = doc
-                        .get_first(field_path)
-                        .and_then(|x| x.as_str().map(PathBuf::from))
-                        .unwrap_or_default();
-                    let updated_at = doc
-                        .get_first(field_updated_at)
-                        .and_then(|x| x.as_datetime())
-                        .and_then(|t| {
-                            chrono::DateTime::from_timestamp_secs(t.into_timestamp_secs())
-                        })
-                        .map(chrono::DateTime::<chrono::Local>::from)
-                        .unwrap_or_default();
-                    let source = doc
-                        .get_first(field_source)
-                        .and_then(|x| x.as_str().map(String::from))
-                        .unwrap_or_default();
-
-                    Doc {
-                        title,
-                        source,
-                        path,
-                        updated_at,
-                    }
-                })
-                .map_err(anyhow::Error::new)
+        .map(|(score, doc_address)| {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            let mut x = read_doc(&schema, &doc)?;
+            x.score = score;
+            x.explanation = match options.explain {
+                true => query
+                    .explain(searcher, doc_address)
+                    .ok()
+                    .and_then(|x| serde_json::to_value(&x).ok())
+                    .map(|e| match options.recency {
+                        Some(recency) if options.sort == Sort::Relevance => {
+                            explain_recency(e, &recency, x.modified_at.timestamp(), now)
+                        }
+                        _ => e,
+                    }),
+                false => None,
+            };
+            x.snippets = match (snippet_generator.as_ref(), doc.get_first(field_body)) {
+                (Some((generator, count)), Some(body)) => {
+                    create_snippets(generator, body.as_str().unwrap_or_default(), *count)
+                }
+                _ => Vec::new(),
+            };
+            Ok(x)
         })
-        .collect()
+        .collect::<tantivy::Result<Vec<_>>>()?;
+
+    Ok(SearchResults {
+        docs,
+        count,
+        terms: terms.into_iter().collect(),
+        explanations,
+        warnings,
+    })
+}
+
+/// Returns the names of the fields, and of the JSON fields searched with paths.
+fn field_names(schema: &Schema) -> (Vec<&str>, Vec<&str>) {
+    let fields = schema.fields().map(|(_, x)| x.name()).collect();
+    let json_fields = schema
+        .fields()
+        .filter(|(_, x)| matches!(x.field_type(), FieldType::JsonObject(_)))
+        .map(|(_, x)| x.name())
+        .collect();
+    (fields, json_fields)
+}
+
+/// Reads the stored fields of a document except the body.
+fn read_doc(schema: &Schema, doc: &TantivyDocument) -> tantivy::Result<Doc> {
+    let text = |name: &str| -> tantivy::Result<String> {
+        Ok(doc
+            .get_first(schema.get_field(name)?)
+            .and_then(|x| x.as_str().map(String::from))
+            .unwrap_or_default())
+    };
+    let date = |name: &str| -> tantivy::Result<chrono::DateTime<Local>> {
+        Ok(doc
+            .get_first(schema.get_field(name)?)
+            .and_then(|x| x.as_datetime())
+            .and_then(|t| chrono::DateTime::from_timestamp_secs(t.into_timestamp_secs()))
+            .map(chrono::DateTime::<Local>::from)
+            .unwrap_or_default())
+    };
+
+    Ok(Doc {
+        index: String::new(),
+        id: text("id")?,
+        score: None,
+        title: text("title")?,
+        updated_at: date("updated_at")?,
+        modified_at: date("modified_at")?,
+        source: text("source")?,
+        path: PathBuf::from(text("path")?),
+        url: doc
+            .get_first(schema.get_field("url")?)
+            .and_then(|x| x.as_str().map(String::from)),
+        body: None,
+        snippets: Vec::new(),
+        explanation: None,
+    })
+}
+
+/// Builds a query requiring documents to match some of the optional clauses at the top level.
+///
+/// The query parser flattens nested disjunctions including those of the default fields, so each
+/// clause is built separately to count the words. Returns `None` for a query of a single clause.
+fn require_words(
+    query_text: &str,
+    minimum_should_match: MinimumShouldMatch,
+    build: impl Fn(UserInputAst) -> Box<dyn Query>,
+) -> Option<Box<dyn Query>> {
+    let UserInputAst::Clause(clauses) = query_grammar::parse_query_lenient(query_text).0 else {
+        return None;
+    };
+    let words = clauses
+        .iter()
+        .filter(|(occur, _)| matches!(occur, None | Some(Occur::Should)))
+        .count();
+    if words < 2 {
+        return None;
+    }
+
+    let subqueries = clauses
+        .into_iter()
+        .map(|(occur, ast)| (occur.unwrap_or(Occur::Should), build(ast)))
+        .collect();
+    let required = minimum_should_match.resolve(words);
+    debug!("Requires {} of {} words to match.", required, words);
+    let mut query = BooleanQuery::new(subqueries);
+    query.set_minimum_number_should_match(required);
+    Some(Box::new(query))
+}
+
+/// Describes the terms looked up by the parsed query, which are analyzed by the tokenizers of
+/// their fields.
+fn explain_query(schema: &Schema, query: &dyn Query, query_text: &str) -> QueryExplanation {
+    let mut terms = Vec::<QueryTerm>::new();
+    query.query_terms(&mut |term, phrase| {
+        let name = schema.get_field_name(term.field());
+        let x = QueryTerm {
+            field: match term.get_json_path() {
+                Some(path) => format!("{}.{}", name, path),
+                None => name.to_string(),
+            },
+            text: term_text(term),
+            phrase,
+        };
+        if !terms.contains(&x) {
+            terms.push(x);
+        }
+    });
+
+    QueryExplanation {
+        index: String::new(),
+        query: query_text.to_string(),
+        terms,
+    }
+}
+
+/// Returns the text of a term, which follows the path for JSON fields.
+fn term_text(term: &Term) -> String {
+    let value = term.value();
+    if let Some(text) = value.as_str() {
+        return text.to_string();
+    }
+    match term.typ() {
+        // The path is followed by a zero byte and the type of the value.
+        Type::Json => value
+            .as_serialized()
+            .splitn(2, |x| *x == 0)
+            .nth(1)
+            .and_then(|x| x.get(1..))
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .unwrap_or_default(),
+        _ => value
+            .as_i64()
+            .map(|x| x.to_string())
+            .or_else(|| value.as_date().map(|x| format!("{:?}", x)))
+            .unwrap_or_default(),
+    }
+}
+
+/// Adds the decay by recency to the explanation of the relevance score.
+fn explain_recency(
+    relevance: serde_json::Value,
+    recency: &Recency,
+    modified_at: i64,
+    now: i64,
+) -> serde_json::Value {
+    let decay = recency.decay(modified_at, now);
+    let value = relevance["value"].as_f64().unwrap_or_default() * decay as f64;
+    serde_json::json!({
+        "value": value,
+        "description": "Recency, product of:",
+        "details": [
+            relevance,
+            {
+                "value": decay,
+                "description": format!(
+                    "1 - weight + weight * 0.5^(age / half_life), modified {:.1} days ago",
+                    (now - modified_at) as f64 / 86400.0
+                ),
+                "context": [
+                    format!("half_life={} days", recency.half_life.num_seconds() as f64 / 86400.0),
+                    format!("weight={}", recency.weight),
+                ],
+            },
+        ],
+    })
+}
+
+/// Creates up to `count` snippets which do not overlap each other.
+///
+/// The best fragment is taken from the body, and the search is repeated on the text before and
+/// after it.
+fn create_snippets(generator: &SnippetGenerator, body: &str, count: usize) -> Vec<Snippet> {
+    let mut snippets = Vec::<(usize, Snippet)>::new();
+    let mut segments = Vec::<Range<usize>>::new();
+    segments.push(0..body.len());
+
+    while snippets.len() < count {
+        let best = segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| (i, generator.snippet(&body[segment.clone()])))
+            .filter(|(_, x)| !x.is_empty())
+            .max_by_key(|(_, x)| x.highlighted().len());
+        let Some((i, snippet)) = best else {
+            break;
+        };
+
+        let segment = segments.remove(i);
+        let offset = body[segment.clone()]
+            .find(snippet.fragment())
+            .map(|x| segment.start + x)
+            .unwrap_or(segment.start);
+        let end = offset + snippet.fragment().len();
+        segments.push(segment.start..offset);
+        segments.push(end..segment.end);
+        segments.retain(|x| !x.is_empty());
+
+        snippets.push((
+            offset,
+            Snippet {
+                fragment: snippet.fragment().to_string(),
+                highlighted: snippet.highlighted().to_vec(),
+            },
+        ));
+    }
+
+    // Keep the order of appearance in the body.
+    snippets.sort_by_key(|(offset, _)| *offset);
+    snippets.into_iter().map(|(_, x)| x).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::config::{FieldConfig, FieldsConfig, SchemaConfig};
+    use crate::index::{Indexer, create_ram_index};
+    use crate::tokenizer::Tokenizers;
+
+    use super::*;
+
+    #[test]
+    fn parse_minimum_should_match() {
+        let parse = |x: &str| x.parse::<MinimumShouldMatch>().unwrap();
+        assert_eq!(parse("2"), MinimumShouldMatch::Count(2));
+        assert_eq!(parse("-1"), MinimumShouldMatch::Count(-1));
+        assert_eq!(parse(" 75% "), MinimumShouldMatch::Percentage(75));
+        assert_eq!(parse("-25%"), MinimumShouldMatch::Percentage(-25));
+    }
+
+    #[test]
+    fn reject_invalid_minimum_should_match() {
+        for x in ["", "%", "two", "1.5", "101%", "-101%", "75%%"] {
+            assert!(x.parse::<MinimumShouldMatch>().is_err(), "{}", x);
+        }
+    }
+
+    #[test]
+    fn resolve_minimum_should_match() {
+        assert_eq!(MinimumShouldMatch::Count(2).resolve(4), 2);
+        assert_eq!(MinimumShouldMatch::Count(-1).resolve(4), 3);
+        assert_eq!(MinimumShouldMatch::Percentage(75).resolve(4), 3);
+        // Rounded up.
+        assert_eq!(MinimumShouldMatch::Percentage(75).resolve(3), 3);
+        assert_eq!(MinimumShouldMatch::Percentage(-25).resolve(3), 3);
+        assert_eq!(MinimumShouldMatch::Percentage(-50).resolve(5), 3);
+    }
+
+    #[test]
+    fn clamp_minimum_should_match() {
+        assert_eq!(MinimumShouldMatch::Count(5).resolve(3), 3);
+        assert_eq!(MinimumShouldMatch::Count(-5).resolve(3), 1);
+        assert_eq!(MinimumShouldMatch::Count(0).resolve(3), 1);
+        assert_eq!(MinimumShouldMatch::Percentage(0).resolve(3), 1);
+    }
+
+    #[test]
+    fn deserialize_minimum_should_match() {
+        let x = serde_json::from_str::<MinimumShouldMatch>("\"75%\"").unwrap();
+        assert_eq!(x, MinimumShouldMatch::Percentage(75));
+        assert!(serde_json::from_str::<MinimumShouldMatch>("\"x\"").is_err());
+    }
+
+    #[test]
+    fn warn_snippets_of_body_not_stored() {
+        let schema_config = SchemaConfig {
+            fields: FieldsConfig {
+                title: None,
+                body: Some(FieldConfig {
+                    tokenizer: "default".to_string(),
+                    stored: false,
+                }),
+            },
+            synonyms: None,
+            fuzzy: None,
+        };
+        let index = create_ram_index(schema_config, &Tokenizers::new(HashMap::new())).unwrap();
+        Indexer::without_timestamps()
+            .ingest(
+                &index,
+                &HashMap::new(),
+                r#"{"id":"1","source":"wiki","body":"Rolling upgrade.","url":"https://example.com/1"}"#.as_bytes(),
+            )
+            .unwrap();
+
+        let options = SearchOptions::new(10).set_snippet(Some(SnippetOptions {
+            max_chars: 100,
+            count: 1,
+        }));
+        let results = search(&index, "upgrade", &options).unwrap();
+        assert_eq!(results.count, 1);
+        assert_eq!(
+            results.warnings[0].to_string(),
+            "Cannot create snippets, the body field is not stored."
+        );
+    }
+
+    #[test]
+    fn explain_terms_of_parsed_query() {
+        let schema_config = SchemaConfig {
+            fields: FieldsConfig {
+                title: None,
+                body: None,
+            },
+            synonyms: None,
+            fuzzy: None,
+        };
+        let index = create_ram_index(schema_config, &Tokenizers::new(HashMap::new())).unwrap();
+
+        let options = SearchOptions::new(10).set_explain(true);
+        let results = search(&index, r#"title:Upgrade "rolling restart""#, &options).unwrap();
+        let terms = results.explanations[0]
+            .terms
+            .iter()
+            .map(|x| (x.field.as_str(), x.text.as_str(), x.phrase))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            terms,
+            [
+                ("title", "upgrade", false),
+                ("title", "rolling", true),
+                ("title", "restart", true),
+                ("body", "rolling", true),
+                ("body", "restart", true),
+            ]
+        );
+    }
 }
@@ -0,0 +1,410 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use clap::ValueEnum;
+use log::{debug, error};
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+use shunbin::{
+    Config, Doc, Error, Filter, FilterError, IndexStats, Indexer, MinimumShouldMatch, Operator,
+    Query, SearchResults, Sort, Workspace, parse_since, parse_until,
+};
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::output::{doc_to_json, to_json};
+
+/// Number of threads handling requests.
+const WORKERS: usize = 4;
+
+#[derive(Debug, Error)]
+pub enum RequestError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<Error> for RequestError {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::IndexNotFound(_) | Error::DocumentNotFound(_) => {
+                Self::NotFound(value.to_string())
+            }
+            Error::InvalidQuery(_) | Error::Filter(_) => Self::BadRequest(value.to_string()),
+            _ => Self::Internal(value.into()),
+        }
+    }
+}
+
+impl RequestError {
+    /// Returns the HTTP status code of the error.
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::BadRequest(_) => 400,
+            Self::NotFound(_) => 404,
+            Self::Conflict(_) => 409,
+            Self::Internal(_) => 500,
+        }
+    }
+}
+
+/// Clears the flag of running indexing when dropped, even if indexing panics.
+struct ReindexingGuard<'a>(&'a AtomicBool);
+
+impl Drop for ReindexingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Parameters of the query string.
+struct Params(Vec<(String, String)>);
+
+impl Params {
+    fn parse(url: &str) -> Self {
+        let query = url.split_once('?').map_or("", |(_, x)| x);
+        Self(
+            form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect(),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn get_all(&self, name: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+            .collect()
+    }
+
+    fn parse_value<T>(&self, name: &str) -> Result<Option<T>, RequestError>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.get(name)
+            .map(|x| {
+                x.parse::<T>().map_err(|e| {
+                    RequestError::BadRequest(format!("Invalid parameter '{}', {}", name, e))
+                })
+            })
+            .transpose()
+    }
+
+    fn parse_enum<T: ValueEnum>(&self, name: &str) -> Result<Option<T>, RequestError> {
+        self.get(name)
+            .map(|x| {
+                T::from_str(x, true).map_err(|e| {
+                    RequestError::BadRequest(format!("Invalid parameter '{}', {}", name, e))
+                })
+            })
+            .transpose()
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.get(name)
+            .is_some_and(|x| matches!(x, "" | "1" | "true" | "yes"))
+    }
+}
+
+/// A search with the options of the `search` command.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchRequest {
+    pub query: String,
+    #[serde(rename = "index", deserialize_with = "one_or_many")]
+    pub indexes: Vec<String>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub snippets: Option<usize>,
+    pub snippet_length: Option<usize>,
+    pub sort: Option<Sort>,
+    pub reverse: bool,
+    #[serde(rename = "source", deserialize_with = "one_or_many")]
+    pub sources: Vec<String>,
+    pub path: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub fuzzy: bool,
+    pub strict: bool,
+    pub operator: Option<Operator>,
+    #[serde(rename = "mm")]
+    pub minimum_should_match: Option<MinimumShouldMatch>,
+}
+
+impl SearchRequest {
+    /// Converts to a query of the indexes, parsing the dates.
+    fn to_query(&self, indexes: Vec<String>) -> Result<Query, FilterError> {
+        let mut query = Query::new(self.query.as_str())
+            .set_indexes(indexes)
+            .set_offset(self.offset)
+            .set_filter(Filter {
+                sources: self.sources.clone(),
+                path: self.path.clone(),
+                since: self.since.as_deref().map(parse_since).transpose()?,
+                until: self.until.as_deref().map(parse_until).transpose()?,
+            })
+            .set_reverse(self.reverse)
+            .set_fuzzy(self.fuzzy)
+            .set_strict(self.strict);
+        if let Some(x) = self.limit {
+            query = query.set_limit(x);
+        }
+        if let Some(x) = self.snippets {
+            query = query.set_snippets(x);
+        }
+        if let Some(x) = self.snippet_length {
+            query = query.set_snippet_length(x);
+        }
+        if let Some(x) = self.sort {
+            query = query.set_sort(x);
+        }
+        if let Some(x) = self.operator {
+            query = query.set_operator(x);
+        }
+        if let Some(x) = self.minimum_should_match {
+            query = query.set_minimum_should_match(x);
+        }
+        Ok(query)
+    }
+
+    fn from_params(params: &Params) -> Result<Self, RequestError> {
+        Ok(Self {
+            query: params.get("q").unwrap_or_default().to_string(),
+            indexes: params.get_all("index"),
+            limit: params.parse_value("limit")?,
+            offset: params.parse_value("offset")?.unwrap_or(0),
+            snippets: params.parse_value("snippets")?,
+            snippet_length: params.parse_value("snippet_length")?,
+            sort: params.parse_enum("sort")?,
+            reverse: params.flag("reverse"),
+            sources: params.get_all("source"),
+            path: params.get("path").map(String::from),
+            since: params.get("since").map(String::from),
+            until: params.get("until").map(String::from),
+            fuzzy: params.flag("fuzzy"),
+            strict: params.flag("strict"),
+            operator: params.parse_enum("operator")?,
+            minimum_should_match: params.parse_value("mm")?,
+        })
+    }
+}
+
+/// Accepts a string as well as an array of strings.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(x) => vec![x],
+        OneOrMany::Many(x) => x,
+    })
+}
+
+/// Serves the indexes in the config file over HTTP with JSON responses.
+///
+/// - `GET /search?q=...`: searches with the parameters named as the options of `search`
+/// - `GET /document?id=...`: gets a document by its id with the stored body
+/// - `GET /stats`: the number of documents and segments of each index
+/// - `POST /reindex`: starts indexing in the background, `full=true` for a full index
+pub struct Server {
+    workspace: Workspace,
+    reindexing: AtomicBool,
+}
+
+impl Server {
+    pub fn new(workspace: Workspace) -> Self {
+        Self {
+            workspace,
+            reindexing: AtomicBool::new(false),
+        }
+    }
+
+    /// Listens on the address, such as `127.0.0.1:8765`, until the process is killed.
+    pub fn listen(self, addr: &str) -> anyhow::Result<()> {
+        let http = Arc::new(
+            tiny_http::Server::http(addr)
+                .map_err(|e| anyhow::anyhow!("Failed to listen on {}, {}", addr, e))?,
+        );
+        let server = Arc::new(self);
+        eprintln!("Listening on http://{}.", addr);
+
+        let workers = (0..WORKERS)
+            .map(|_| {
+                let http = http.clone();
+                let server = server.clone();
+                thread::spawn(move || {
+                    for request in http.incoming_requests() {
+                        server.handle(request);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            let _ = worker.join();
+        }
+
+        Ok(())
+    }
+
+    fn handle(self: &Arc<Self>, request: Request) {
+        let start_at = Instant::now();
+        let url = request.url().to_string();
+        let path = url.split_once('?').map_or(url.as_str(), |(x, _)| x);
+        let params = Params::parse(url.as_str());
+
+        let result = match (request.method(), path) {
+            (Method::Get, "/search") => SearchRequest::from_params(&params)
+                .and_then(|x| self.search(&x))
+                .and_then(
+                    |x| Ok(to_json(&x, &self.config().indexes).map_err(anyhow::Error::from)?),
+                ),
+            (Method::Get, "/document") => self.document(&params),
+            (Method::Get, "/stats") => self.stats(),
+            (Method::Post, "/reindex") => self.reindex(&params),
+            _ => Err(RequestError::NotFound(format!(
+                "Not found {} {}.",
+                request.method(),
+                path
+            ))),
+        };
+        let (status, body) = match result {
+            Ok(x) => (200, x),
+            Err(e) => {
+                if let RequestError::Internal(x) = &e {
+                    error!("{}", x);
+                }
+                (e.status(), json!({ "error": e.to_string() }))
+            }
+        };
+        debug!(
+            "{} {} {} in {:?}.",
+            request.method(),
+            url,
+            status,
+            start_at.elapsed()
+        );
+
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+        if let Err(e) = request.respond(response) {
+            error!("Failed to respond, {}", e);
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        self.workspace.config()
+    }
+
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
+    }
+
+    /// Returns the given names of the indexes, the default index or all indexes.
+    pub fn index_names(&self, indexes: &[String]) -> Result<Vec<String>, RequestError> {
+        let config = self.config();
+        match indexes.is_empty() && config.get_default_search_index_name().is_err() {
+            true => Ok(self.workspace.all_index_names()),
+            false => Ok(self.workspace.index_names(indexes)?),
+        }
+    }
+
+    /// Searches the indexes, using the defaults in the config file for the missing options.
+    pub fn search(&self, request: &SearchRequest) -> Result<SearchResults, RequestError> {
+        let query = request
+            .to_query(self.index_names(&request.indexes)?)
+            .map_err(|e| RequestError::BadRequest(e.to_string()))?;
+        Ok(self.workspace.search(&query)?)
+    }
+
+    /// Finds a document by the id in the indexes, or in all indexes if none is given.
+    pub fn find_document(&self, indexes: &[String], id: &str) -> Result<Doc, RequestError> {
+        Ok(self.workspace.find_document(indexes, id)?)
+    }
+
+    fn document(&self, params: &Params) -> Result<serde_json::Value, RequestError> {
+        let id = params
+            .get("id")
+            .ok_or_else(|| RequestError::BadRequest("Missing parameter 'id'.".to_string()))?;
+        let doc = self.find_document(&params.get_all("index"), id)?;
+        Ok(doc_to_json(&doc, &self.config().indexes[&doc.index])?)
+    }
+
+    pub fn stats(&self) -> Result<serde_json::Value, RequestError> {
+        let indexes = self
+            .workspace
+            .all_index_names()
+            .iter()
+            .map(|x| self.workspace.index_stats(x))
+            .collect::<Result<Vec<IndexStats>, _>>()?;
+
+        Ok(json!({
+            "indexes": indexes,
+            "reindexing": self.reindexing.load(Ordering::SeqCst),
+        }))
+    }
+
+    /// Starts indexing in a thread, and the readers pick up the commits shortly.
+    fn reindex(self: &Arc<Self>, params: &Params) -> Result<serde_json::Value, RequestError> {
+        let index_names = match params.get_all("index") {
+            x if x.is_empty() => self.workspace.all_index_names(),
+            x => self.index_names(&x)?,
+        };
+        let full = params.flag("full");
+
+        if self.reindexing.swap(true, Ordering::SeqCst) {
+            return Err(RequestError::Conflict(
+                "Indexing is already running.".to_string(),
+            ));
+        }
+
+        let server = self.clone();
+        let names = index_names.clone();
+        thread::spawn(move || {
+            let _reindexing = ReindexingGuard(&server.reindexing);
+            let start_at = Instant::now();
+            let mut indexer = Indexer::new();
+            indexer = match full || !indexer.is_incrementable() {
+                true => indexer.set_increment(false),
+                false => indexer.set_increment(true),
+            };
+            for index_name in names {
+                let index_config = &server.config().indexes[&index_name];
+                let result = server
+                    .workspace
+                    .index(index_name.as_str())
+                    .and_then(|x| indexer.index(index_name.clone(), &x, index_config));
+                if let Err(e) = result {
+                    error!("Failed to index {}, {}", index_name, e);
+                }
+            }
+            eprintln!(
+                "{} documents were indexed in {:?}.",
+                indexer.indexed_count(),
+                start_at.elapsed()
+            );
+        });
+
+        Ok(json!({ "indexes": index_names, "full": full }))
+    }
+}
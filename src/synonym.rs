@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use log::warn;
+use tantivy::tokenizer::TextAnalyzer;
+
+use crate::config::SynonymsConfig;
+use crate::error::Error;
+
+const DEFAULT_BOOST: f32 = 0.5;
+
+/// Synonyms loaded from a file in the Solr synonyms format.
+///
+/// ```text
+/// # Equivalent synonyms, each term expands to all the others.
+/// 認証基盤, auth, IdP
+/// # One-way mapping, "k8s" expands to "kubernetes" but not vice versa.
+/// k8s => kubernetes
+/// ```
+#[derive(Clone, Debug)]
+pub struct Synonyms {
+    map: HashMap<String, Vec<String>>,
+    /// Length of the longest term in bytes, to bound the spans of tokens to look up.
+    max_len: usize,
+    boost: f32,
+}
+
+impl Synonyms {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path).map_err(|e| Error::Read {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        Ok(Self::parse(content.as_str()))
+    }
+
+    /// Loads the synonyms file of a schema with its boost.
+    pub fn from_config(config: &SynonymsConfig) -> Result<Self, Error> {
+        let synonyms = Self::load(config.path.as_path())?;
+        Ok(match config.boost {
+            Some(boost) => synonyms.set_boost(boost),
+            None => synonyms,
+        })
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut map = HashMap::<String, Vec<String>>::new();
+
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (from, to) = match line.split_once("=>") {
+                Some((from, to)) => (split_terms(from), split_terms(to)),
+                None => {
+                    let terms = split_terms(line);
+                    (terms.clone(), terms)
+                }
+            };
+            if from.is_empty() || to.is_empty() {
+                warn!("Skip line {} of synonyms, no terms on one side", n + 1);
+                continue;
+            }
+
+            for key in from {
+                let entry = map.entry(normalize(key.as_str())).or_default();
+                to.iter()
+                    .filter(|x| normalize(x) != normalize(key.as_str()))
+                    .for_each(|x| {
+                        if !entry.contains(x) {
+                            entry.push(x.clone());
+                        }
+                    });
+            }
+        }
+
+        let max_len = map.keys().map(String::len).max().unwrap_or_default();
+
+        Self {
+            map,
+            max_len,
+            boost: DEFAULT_BOOST,
+        }
+    }
+
+    pub fn set_boost(mut self, boost: f32) -> Self {
+        self.boost = boost;
+        self
+    }
+
+    /// Rewrites the query so that each term also matches its synonyms.
+    ///
+    /// Terms are matched on the boundaries of the tokens of `analyzer`, so that a term is found
+    /// in a word not separated by spaces, such as `認証基盤` in `認証基盤の設定`. A matched word
+    /// `auth` becomes `(auth OR "認証基盤"^0.5 OR "IdP"^0.5)`, and the other synonyms replace the
+    /// term within the word, such as `(認証基盤の設定 OR "authの設定"^0.5 OR …)`.
+    /// Words using the query syntax, such as `title:auth` or `"auth"`, and words in phrases are
+    /// left as they are.
+    pub fn expand(&self, query: &str, analyzer: &mut TextAnalyzer) -> String {
+        let words: Vec<&str> = query.split_whitespace().collect();
+
+        let mut in_quotes = false;
+        let plain = words
+            .iter()
+            .map(|x| {
+                let quoted = in_quotes || x.contains('"');
+                in_quotes ^= x.matches('"').count() % 2 == 1;
+                !quoted && is_plain_term(x)
+            })
+            .collect::<Vec<_>>();
+
+        // Runs of plain words are looked up together, for terms of multiple words.
+        let mut expanded = Vec::<String>::new();
+        let mut i = 0;
+        while i < words.len() {
+            if !plain[i] {
+                expanded.push(words[i].to_string());
+                i += 1;
+                continue;
+            }
+            let end = (i..words.len()).find(|x| !plain[*x]).unwrap_or(words.len());
+            expanded.extend(self.expand_words(&words[i..end], analyzer));
+            i = end;
+        }
+
+        expanded.join(" ")
+    }
+
+    fn expand_words(&self, words: &[&str], analyzer: &mut TextAnalyzer) -> Vec<String> {
+        let text = words.join(" ");
+        // Byte ranges of the words in the text.
+        let mut bounds = Vec::<(usize, usize)>::new();
+        for word in words {
+            let start = bounds.last().map_or(0, |x| x.1 + 1);
+            bounds.push((start, start + word.len()));
+        }
+
+        let mut offsets = Vec::<(usize, usize)>::new();
+        let mut stream = analyzer.token_stream(text.as_str());
+        while stream.advance() {
+            let token = stream.token();
+            offsets.push((token.offset_from, token.offset_to));
+        }
+
+        // The longest term from each token, not overlapping the previous match.
+        let mut matches = Vec::<(usize, usize, &Vec<String>)>::new();
+        let mut i = 0;
+        while i < offsets.len() {
+            let start = offsets[i].0;
+            let count = offsets[i..]
+                .iter()
+                .take_while(|x| x.1.saturating_sub(start) <= self.max_len)
+                .count();
+            let matched = offsets[i..i + count].iter().rev().find_map(|x| {
+                let key = normalize(&text[start..x.1]);
+                self.map
+                    .get(key.as_str())
+                    .map(|synonyms| (start, x.1, synonyms))
+            });
+            match matched {
+                Some(x) => {
+                    i = offsets[i..]
+                        .iter()
+                        .position(|y| y.0 >= x.1)
+                        .map_or(offsets.len(), |n| i + n);
+                    matches.push(x);
+                }
+                None => i += 1,
+            }
+        }
+
+        let word_at = |offset: usize| {
+            bounds
+                .iter()
+                .position(|x| offset <= x.1)
+                .unwrap_or_default()
+        };
+        let mut expanded = Vec::<String>::new();
+        let mut i = 0;
+        while i < words.len() {
+            // A term of multiple words joins the words into a group.
+            let mut end = i;
+            for (start, to, _) in matches.iter() {
+                if word_at(*start) <= end && word_at(*to) > end {
+                    end = word_at(*to);
+                }
+            }
+            let (from, to) = (bounds[i].0, bounds[end].1);
+            let alternatives = matches
+                .iter()
+                .filter(|x| from <= x.0 && x.1 <= to)
+                .flat_map(|(start, end, synonyms)| {
+                    synonyms.iter().map(|x| {
+                        format!(
+                            "\"{}{}{}\"^{}",
+                            &text[from..*start],
+                            x,
+                            &text[*end..to],
+                            self.boost
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let original = match end - i {
+                0 => words[i].to_string(),
+                _ => format!("({})", words[i..=end].join(" ")),
+            };
+            match alternatives.is_empty() {
+                true => expanded.push(original),
+                false => expanded.push(format!("({} OR {})", original, alternatives.join(" OR "))),
+            }
+            i = end + 1;
+        }
+
+        expanded
+    }
+}
+
+fn split_terms(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|x| x.split_whitespace().collect::<Vec<_>>().join(" "))
+        .map(|x| x.replace('"', ""))
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+fn normalize(s: &str) -> String {
+    s.to_lowercase()
+}
+
+fn is_plain_term(word: &str) -> bool {
+    !word.contains([
+        ':', '"', '\'', '(', ')', '[', ']', '{', '}', '^', '*', '~', '\\',
+    ]) && !word.starts_with(['+', '-'])
+        && !matches!(word, "AND" | "OR" | "NOT" | "IN")
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::tokenizer::{LowerCaser, RegexTokenizer, SimpleTokenizer};
+
+    use super::*;
+
+    fn synonyms() -> Synonyms {
+        Synonyms::parse(
+            "# comment\n\
+             認証基盤, auth, IdP\n\
+             k8s => kubernetes\n\
+             single sign on, SSO\n",
+        )
+    }
+
+    fn expand(query: &str) -> String {
+        let mut analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .build();
+        synonyms().expand(query, &mut analyzer)
+    }
+
+    #[test]
+    fn parse_equivalent_synonyms() {
+        let synonyms = synonyms();
+        assert_eq!(synonyms.map["auth"], vec!["認証基盤", "IdP"]);
+        assert_eq!(synonyms.map["idp"], vec!["認証基盤", "auth"]);
+        assert_eq!(synonyms.map["認証基盤"], vec!["auth", "IdP"]);
+    }
+
+    #[test]
+    fn parse_one_way_synonyms() {
+        let synonyms = synonyms();
+        assert_eq!(synonyms.map["k8s"], vec!["kubernetes"]);
+        assert!(!synonyms.map.contains_key("kubernetes"));
+    }
+
+    #[test]
+    fn parse_multi_word_synonyms() {
+        let synonyms = synonyms();
+        assert_eq!(synonyms.map["single sign on"], vec!["SSO"]);
+        assert_eq!(synonyms.max_len, "single sign on".len());
+    }
+
+    #[test]
+    fn parse_skips_lines_without_terms() {
+        let synonyms = Synonyms::parse("=> a\nb =>\n,\n");
+        assert!(synonyms.map.is_empty());
+    }
+
+    #[test]
+    fn expand_term() {
+        assert_eq!(expand("k8s upgrade"), "(k8s OR \"kubernetes\"^0.5) upgrade");
+    }
+
+    #[test]
+    fn expand_multi_word_match() {
+        assert_eq!(
+            expand("Single Sign On setup"),
+            "((Single Sign On) OR \"SSO\"^0.5) setup"
+        );
+    }
+
+    #[test]
+    fn expand_leaves_quoted_words() {
+        assert_eq!(expand("\"auth\""), "\"auth\"");
+        assert_eq!(
+            expand("\"the auth service\" k8s"),
+            "\"the auth service\" (k8s OR \"kubernetes\"^0.5)"
+        );
+        assert_eq!(expand("\"single sign on\""), "\"single sign on\"");
+    }
+
+    #[test]
+    fn expand_leaves_field_prefixed_words() {
+        assert_eq!(expand("title:auth"), "title:auth");
+        assert_eq!(expand("+k8s -auth"), "+k8s -auth");
+    }
+
+    #[test]
+    fn expand_term_in_word() {
+        // Segments words as a Japanese tokenizer does.
+        let tokenizer = RegexTokenizer::new("認証|基盤|の|設定|[a-z0-9]+").unwrap();
+        let mut analyzer = TextAnalyzer::from(tokenizer);
+        assert_eq!(
+            synonyms().expand("認証基盤の設定", &mut analyzer),
+            "(認証基盤の設定 OR \"authの設定\"^0.5 OR \"IdPの設定\"^0.5)"
+        );
+        assert_eq!(synonyms().expand("認証の設定", &mut analyzer), "認証の設定");
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A part of the query which is not valid syntax.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxWarning {
+    pub query: String,
+    /// Index of the offending character in the query, counted in characters.
+    pub position: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.", self.message)?;
+        if let Some(position) = self.position {
+            let width = self.query.chars().take(position).map(char_width).sum();
+            write!(f, "\n    {}\n    {}^", self.query, " ".repeat(width))?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes invalid syntax in the query, so the query parser reads it as literal text.
+///
+/// Unbalanced quotes and brackets, colons not following a field name, such as `注意:` and
+/// `https://`, and boolean operators without a term on one side are escaped. A full-width colon
+/// following a field name, such as `title：認証`, is read as a colon. The names of `json_fields`
+/// are followed by paths, such as `fields.channel:`.
+pub fn repair(query: &str, fields: &[&str], json_fields: &[&str]) -> (String, Vec<SyntaxWarning>) {
+    let mut chars: Vec<char> = query.chars().collect();
+    let mut escaped = BTreeSet::<usize>::new();
+    let mut warnings = Vec::<SyntaxWarning>::new();
+    let mut warn = |position: usize, message: String| {
+        warnings.push(SyntaxWarning {
+            query: query.to_string(),
+            position: Some(position),
+            message,
+        })
+    };
+
+    let mut quote = None;
+    let mut brackets = Vec::<usize>::new();
+    let mut word_start = 0;
+    let mut has_field = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' {
+            i += 2;
+            continue;
+        }
+        if c.is_whitespace() {
+            word_start = i + 1;
+            has_field = false;
+            i += 1;
+            continue;
+        }
+        if quote.is_some() {
+            if c == '"' {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '"' => quote = Some(i),
+            '(' | '[' | '{' => brackets.push(i),
+            ')' | ']' | '}' => match brackets.pop() {
+                Some(open) if is_pair(chars[open], c) => {
+                    if c != ')' && !is_range(&chars[open + 1..i]) {
+                        escaped.extend([open, i]);
+                        warn(
+                            open,
+                            format!("'{}' does not start a range such as [a TO b]", chars[open]),
+                        );
+                    }
+                }
+                open => {
+                    if let Some(x) = open {
+                        brackets.push(x);
+                    }
+                    escaped.insert(i);
+                    warn(i, format!("Unmatched '{}'", c));
+                }
+            },
+            // Dates in ranges such as `[2025-01-01T00:00:00Z TO *]` contain colons.
+            ':' | '：' if !brackets.iter().any(|x| chars[*x] != '(') => {
+                let name: String = chars[word_start..i]
+                    .iter()
+                    .skip_while(|x| matches!(x, '+' | '-' | '('))
+                    .collect();
+                let is_field = !has_field
+                    && (fields.contains(&name.as_str())
+                        || name
+                            .split_once('.')
+                            .is_some_and(|(x, path)| json_fields.contains(&x) && !path.is_empty()));
+                let is_last = chars[i + 1..].iter().all(|x| x.is_whitespace());
+                if is_field && !is_last {
+                    chars[i] = ':';
+                    has_field = true;
+                } else if c == ':' {
+                    escaped.insert(i);
+                    let message = if is_field {
+                        format!("'{}:' is not followed by a term", name)
+                    } else if has_field || name.is_empty() {
+                        "':' does not follow a field name".to_string()
+                    } else {
+                        format!("Unknown field '{}'", name)
+                    };
+                    warn(i, message);
+                }
+            }
+            '^' if !chars.get(i + 1).is_some_and(|x| x.is_ascii_digit()) => {
+                escaped.insert(i);
+                warn(i, "'^' is not followed by a boost".to_string());
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if let Some(x) = quote {
+        escaped.insert(x);
+        warn(x, "Unbalanced '\"'".to_string());
+    }
+    for x in brackets {
+        escaped.insert(x);
+        warn(x, format!("Unmatched '{}'", chars[x]));
+    }
+
+    let mut repaired = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if escaped.contains(&i) {
+            repaired.push('\\');
+        }
+        repaired.push(*c);
+    }
+
+    // Operators are keywords which cannot be escaped, so they are quoted instead.
+    let words: Vec<&str> = repaired.split_whitespace().collect();
+    let mut quoted = Vec::<String>::new();
+    for (n, word) in words.iter().enumerate() {
+        let is_operator = matches!(*word, "AND" | "OR");
+        let dangling = match n {
+            0 => is_operator || *word == "NOT" && words.len() == 1,
+            _ if n == words.len() - 1 => is_operator || *word == "NOT",
+            _ => is_operator && matches!(words[n - 1], "AND" | "OR" | "NOT"),
+        };
+        if dangling {
+            warnings.push(SyntaxWarning {
+                query: query.to_string(),
+                position: find_word(query, n),
+                message: format!("'{}' has no term on one side", word),
+            });
+            quoted.push(format!("\"{}\"", word));
+        } else {
+            quoted.push(word.to_string());
+        }
+    }
+
+    warnings.sort_by_key(|x| x.position);
+    (quoted.join(" "), warnings)
+}
+
+fn is_pair(open: char, close: char) -> bool {
+    match open {
+        '(' => close == ')',
+        _ => matches!(close, ']' | '}'),
+    }
+}
+
+fn is_range(inner: &[char]) -> bool {
+    let inner: String = inner.iter().collect();
+    inner.split_whitespace().filter(|x| *x == "TO").count() == 1
+}
+
+/// Returns the position of the `n`th word in characters.
+fn find_word(query: &str, n: usize) -> Option<usize> {
+    let mut count = 0;
+    let mut in_word = false;
+    for (i, c) in query.chars().enumerate() {
+        if c.is_whitespace() {
+            in_word = false;
+        } else if !in_word {
+            if count == n {
+                return Some(i);
+            }
+            count += 1;
+            in_word = true;
+        }
+    }
+    None
+}
+
+/// Returns the number of columns a character takes in a terminal.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: [&str; 4] = ["title", "body", "source", "updated_at"];
+    const JSON_FIELDS: [&str; 1] = ["fields"];
+
+    fn repaired(query: &str) -> String {
+        repair(query, &FIELDS, &JSON_FIELDS).0
+    }
+
+    fn positions(query: &str) -> Vec<Option<usize>> {
+        let (_, warnings) = repair(query, &FIELDS, &JSON_FIELDS);
+        warnings.iter().map(|x| x.position).collect()
+    }
+
+    #[test]
+    fn keep_valid_queries() {
+        for query in [
+            "auth token",
+            "title:認証 AND body:\"token flow\"",
+            "+auth -(legacy OR old)",
+            "updated_at:[2025-01-01T00:00:00Z TO *]",
+            "auth^2 fields.channel:ops",
+        ] {
+            assert_eq!(
+                repair(query, &FIELDS, &JSON_FIELDS),
+                (query.to_string(), vec![])
+            );
+        }
+    }
+
+    #[test]
+    fn escape_unbalanced_quotes_and_brackets() {
+        assert_eq!(repaired("\"auth token"), "\\\"auth token");
+        assert_eq!(repaired("(auth token"), "\\(auth token");
+        assert_eq!(repaired("auth) token"), "auth\\) token");
+        assert_eq!(repaired("[auth]"), "\\[auth\\]");
+        assert_eq!(positions("auth) (token"), vec![Some(4), Some(6)]);
+    }
+
+    #[test]
+    fn escape_colons_not_following_fields() {
+        assert_eq!(
+            repaired("注意: https://example.com"),
+            "注意\\: https\\://example.com"
+        );
+        assert_eq!(repaired("title:"), "title\\:");
+        assert_eq!(repaired("title:a:b"), "title:a\\:b");
+        assert_eq!(repaired("fields.:x"), "fields.\\:x");
+    }
+
+    #[test]
+    fn read_full_width_colons_after_fields() {
+        assert_eq!(repaired("title：認証"), "title:認証");
+        assert_eq!(repaired("注意：認証"), "注意：認証");
+    }
+
+    #[test]
+    fn escape_carets_without_boosts() {
+        assert_eq!(repaired("a^b"), "a\\^b");
+        assert_eq!(positions("a^b"), vec![Some(1)]);
+    }
+
+    #[test]
+    fn quote_dangling_operators() {
+        assert_eq!(repaired("AND auth"), "\"AND\" auth");
+        assert_eq!(repaired("auth OR"), "auth \"OR\"");
+        assert_eq!(repaired("auth AND OR token"), "auth AND \"OR\" token");
+        assert_eq!(repaired("NOT"), "\"NOT\"");
+        assert_eq!(positions("auth AND OR token"), vec![Some(9)]);
+    }
+
+    #[test]
+    fn display_position_by_width() {
+        let (_, warnings) = repair("認証 )", &FIELDS, &JSON_FIELDS);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Unmatched ')'.\n    認証 )\n         ^"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use log::{debug, warn};
+use sudachi::dic::dictionary::JapaneseDictionary;
+use sudachi_tantivy::SudachiTokenizer;
+use tantivy::schema::{FieldType, Schema};
+use tantivy::tokenizer::{RawTokenizer, TokenizerManager};
+
+use crate::config::TokenizerConfig;
+use crate::error::Error;
+
+/// Tokenizers configured in the config file.
+///
+/// Tokenizers are registered to an index only when its schema uses them,
+/// and each Sudachi dictionary is loaded at most once per process.
+pub struct Tokenizers {
+    configs: HashMap<String, TokenizerConfig>,
+    dicts: Mutex<HashMap<PathBuf, Arc<JapaneseDictionary>>>,
+}
+
+impl Tokenizers {
+    pub fn new(configs: HashMap<String, TokenizerConfig>) -> Self {
+        Self {
+            configs,
+            dicts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether the tokenizer is configured in the config file or built in tantivy.
+    pub fn contains(&self, tokenizer_name: &str) -> bool {
+        self.configs.contains_key(tokenizer_name)
+            || TokenizerManager::default().get(tokenizer_name).is_some()
+    }
+
+    /// Registers the tokenizers referenced by the schema of `index`.
+    pub fn register_for(&self, index: &tantivy::Index) -> Result<(), Error> {
+        tokenizer_names(&index.schema())
+            .into_iter()
+            .try_for_each(|name| self.register(index, name.as_str()))
+    }
+
+    fn register(&self, index: &tantivy::Index, tokenizer_name: &str) -> Result<(), Error> {
+        let Some(config) = self.configs.get(tokenizer_name) else {
+            if index.tokenizers().get(tokenizer_name).is_none() {
+                warn!("Tokenizer '{tokenizer_name}' is not configured.");
+            }
+            return Ok(());
+        };
+
+        match config {
+            TokenizerConfig::Sudachi {
+                dict,
+                user_dict: _,
+                mode,
+            } => {
+                let mut tokenizer = SudachiTokenizer::new(self.dictionary(dict)?);
+                tokenizer.set_mode(mode.clone().into());
+                index.tokenizers().register(tokenizer_name, tokenizer);
+            }
+            TokenizerConfig::Raw => index
+                .tokenizers()
+                .register(tokenizer_name, RawTokenizer::default()),
+        }
+
+        Ok(())
+    }
+
+    fn dictionary(&self, dict: &Path) -> Result<Arc<JapaneseDictionary>, Error> {
+        let mut dicts = self.dicts.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(x) = dicts.get(dict) {
+            return Ok(x.clone());
+        }
+
+        let start_at = Instant::now();
+        let error = |e: &dyn std::fmt::Display| Error::Dictionary {
+            path: dict.to_path_buf(),
+            message: e.to_string(),
+        };
+        let sudachi_config = sudachi::config::Config::new(None, None, Some(dict.to_path_buf()))
+            .map_err(|e| error(&e))?;
+        let jp_dict =
+            Arc::new(JapaneseDictionary::from_cfg(&sudachi_config).map_err(|e| error(&e))?);
+        debug!(
+            "Loaded the dictionary {:?} in {:?}.",
+            dict,
+            start_at.elapsed()
+        );
+
+        dicts.insert(dict.to_path_buf(), jp_dict.clone());
+        Ok(jp_dict)
+    }
+}
+
+/// Returns the names of tokenizers used by the text fields of the schema.
+fn tokenizer_names(schema: &Schema) -> BTreeSet<String> {
+    schema
+        .fields()
+        .filter_map(|(_, entry)| match entry.field_type() {
+            FieldType::Str(options) => options
+                .get_indexing_options()
+                .map(|x| x.tokenizer().to_string()),
+            _ => None,
+        })
+        .collect()
+}
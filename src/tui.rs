@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use chrono::{TimeDelta, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use shunbin::{Doc, Filter, Location, Query, Sort, Workspace};
+
+/// Time to wait for the next keystroke before searching.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Choices of `--since` toggled by a key, in days.
+const SINCE_DAYS: [Option<i64>; 5] = [None, Some(1), Some(7), Some(30), Some(365)];
+
+/// Lines of the selected document with the locations of the query terms.
+///
+/// Documents ingested with URLs are previewed by their stored bodies, and have no paths to open.
+struct Preview {
+    id: String,
+    location: String,
+    path: Option<PathBuf>,
+    lines: Vec<String>,
+    locations: Vec<Location>,
+}
+
+struct App {
+    workspace: Workspace,
+    index_names: Vec<String>,
+    current: usize,
+    limit: usize,
+    query: String,
+    changed_at: Option<Instant>,
+    docs: Vec<Doc>,
+    count: usize,
+    terms: Vec<String>,
+    list_state: ListState,
+    preview: Option<Preview>,
+    scroll: u16,
+    source: Option<String>,
+    since: usize,
+    status: String,
+}
+
+/// Runs the interactive search of the indexes until Esc or Ctrl-C is pressed.
+pub fn run(workspace: Workspace, index_names: Vec<String>, limit: usize) -> anyhow::Result<()> {
+    if index_names.is_empty() {
+        anyhow::bail!("No index to search.");
+    }
+    // Opened before the terminal is taken over, to print the errors as usual.
+    for index_name in index_names.iter() {
+        workspace.index(index_name)?;
+    }
+
+    let mut app = App {
+        workspace,
+        index_names,
+        current: 0,
+        limit,
+        query: String::new(),
+        // Show the recently modified documents first.
+        changed_at: Some(Instant::now() - DEBOUNCE),
+        docs: Vec::new(),
+        count: 0,
+        terms: Vec::new(),
+        list_state: ListState::default(),
+        preview: None,
+        scroll: 0,
+        source: None,
+        since: 0,
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.main_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn main_loop(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let timeout = match self.changed_at {
+                Some(x) => DEBOUNCE.saturating_sub(x.elapsed()),
+                None => Duration::from_secs(1),
+            };
+            if event::poll(timeout)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key, terminal)?
+            {
+                return Ok(());
+            }
+
+            if self.changed_at.is_some_and(|x| x.elapsed() >= DEBOUNCE) {
+                self.changed_at = None;
+                self.search();
+            }
+        }
+    }
+
+    /// Handles a key, and returns false to quit.
+    fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        terminal: &mut DefaultTerminal,
+    ) -> anyhow::Result<bool> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if ctrl => return Ok(false),
+            KeyCode::Enter => self.open(terminal)?,
+            KeyCode::Up => self.select(-1),
+            KeyCode::Char('p') if ctrl => self.select(-1),
+            KeyCode::Down => self.select(1),
+            KeyCode::Char('n') if ctrl => self.select(1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('b') if ctrl => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::Char('f') if ctrl => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::Tab => {
+                self.current = (self.current + 1) % self.index_names.len();
+                self.source = None;
+                self.changed();
+            }
+            KeyCode::Char('s') if ctrl => {
+                let mut sources = self.workspace.config().indexes[&self.index_names[self.current]]
+                    .sources
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                sources.sort();
+                self.source = match self.source.as_ref() {
+                    None => sources.first().cloned(),
+                    Some(x) => sources
+                        .iter()
+                        .position(|y| y == x)
+                        .and_then(|i| sources.get(i + 1))
+                        .cloned(),
+                };
+                self.changed();
+            }
+            KeyCode::Char('t') if ctrl => {
+                self.since = (self.since + 1) % SINCE_DAYS.len();
+                self.changed();
+            }
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.changed();
+            }
+            KeyCode::Char('w') if ctrl => {
+                let end = self.query.trim_end().rfind(' ').map_or(0, |x| x + 1);
+                self.query.truncate(end);
+                self.changed();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.changed();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.changed();
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn changed(&mut self) {
+        self.changed_at = Some(Instant::now());
+    }
+
+    fn search(&mut self) {
+        let filter = Filter {
+            sources: self.source.iter().cloned().collect(),
+            since: SINCE_DAYS[self.since].map(|x| Utc::now() - TimeDelta::days(x)),
+            ..Filter::default()
+        };
+        let sort = match self.query.trim().is_empty() {
+            true => Sort::Modified,
+            false => Sort::Relevance,
+        };
+        let query = Query::new(self.query.as_str())
+            .set_indexes(vec![self.index_names[self.current].clone()])
+            .set_limit(self.limit)
+            .set_snippets(0)
+            .set_filter(filter)
+            .set_sort(sort);
+
+        let start_at = Instant::now();
+        match self.workspace.search(&query) {
+            Ok(results) => {
+                self.status = match results.warnings.first() {
+                    Some(x) => x.message.clone(),
+                    None => format!(
+                        "{} of {} matching documents in {:?}.",
+                        results.docs.len(),
+                        results.count,
+                        start_at.elapsed()
+                    ),
+                };
+                self.docs = results.docs;
+                self.count = results.count;
+                self.terms = results.terms;
+            }
+            Err(e) => {
+                self.status = e.to_string();
+                self.docs.clear();
+                self.count = 0;
+                self.terms.clear();
+            }
+        }
+
+        self.list_state
+            .select(if self.docs.is_empty() { None } else { Some(0) });
+        self.update_preview();
+    }
+
+    fn select(&mut self, delta: isize) {
+        if self.docs.is_empty() {
+            return;
+        }
+        let selected = self.list_state.selected().unwrap_or_default();
+        let selected = selected
+            .saturating_add_signed(delta)
+            .min(self.docs.len() - 1);
+        self.list_state.select(Some(selected));
+        self.update_preview();
+    }
+
+    fn update_preview(&mut self) {
+        let Some(doc) = self.list_state.selected().and_then(|x| self.docs.get(x)) else {
+            self.preview = None;
+            return;
+        };
+        if self.preview.as_ref().is_some_and(|x| x.id == doc.id) {
+            return;
+        }
+
+        let index_name = &self.index_names[self.current];
+        let sources = &self.workspace.config().indexes[index_name].sources;
+        let preview = (|| {
+            let (path, text) = match doc.url.as_ref() {
+                Some(url) => {
+                    let doc = self
+                        .workspace
+                        .find_document(std::slice::from_ref(index_name), &doc.id)?;
+                    let Some(body) = doc.body else {
+                        anyhow::bail!("Cannot preview {}, the body is not stored.", url);
+                    };
+                    (None, body)
+                }
+                None => {
+                    let path = doc.absolute_path(sources)?;
+                    let text = std::fs::read_to_string(&path)?;
+                    (Some(path), text)
+                }
+            };
+            let locations = self
+                .workspace
+                .locator(index_name, self.terms.clone())?
+                .locate(text.as_str());
+            anyhow::Ok(Preview {
+                id: doc.id.clone(),
+                location: doc.location(sources)?,
+                path,
+                lines: text.lines().map(String::from).collect(),
+                locations,
+            })
+        })();
+
+        match preview {
+            Ok(x) => {
+                // Show a few lines before the first match.
+                self.scroll = x
+                    .locations
+                    .first()
+                    .map(|x| x.line.saturating_sub(3) as u16)
+                    .unwrap_or_default();
+                self.preview = Some(x);
+            }
+            Err(e) => {
+                self.status = e.to_string();
+                self.preview = None;
+            }
+        }
+    }
+
+    /// Opens the selected document in `$EDITOR` at the first matching line.
+    fn open(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        let Some(preview) = self.preview.as_ref() else {
+            return Ok(());
+        };
+        let Some(path) = preview.path.as_ref() else {
+            self.status = format!("Cannot open {} in the editor.", preview.location);
+            return Ok(());
+        };
+        let line = preview.locations.first().map_or(1, |x| x.line);
+        let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+        let mut words = editor.split_whitespace();
+        let Some(program) = words.next() else {
+            return Ok(());
+        };
+
+        ratatui::restore();
+        let status = Command::new(program)
+            .args(words)
+            .arg(format!("+{}", line))
+            .arg(path)
+            .status();
+        *terminal = ratatui::init();
+        terminal.clear()?;
+
+        match status {
+            Ok(x) if !x.success() => self.status = format!("{} exited with {}.", editor, x),
+            Err(e) => self.status = format!("Failed to run {}, {}", editor, e),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [input_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main_area);
+
+        let mut conditions = vec![self.index_names[self.current].clone()];
+        if let Some(x) = self.source.as_ref() {
+            conditions.push(format!("source:{}", x));
+        }
+        if let Some(x) = SINCE_DAYS[self.since] {
+            conditions.push(format!("since:{}d", x));
+        }
+        let input = Line::from(vec![
+            Span::raw("> ").bold(),
+            Span::raw(self.query.as_str()),
+            Span::raw(format!("  [{}]", conditions.join(" "))).dim(),
+        ]);
+        frame.render_widget(Paragraph::new(input), input_area);
+        frame.set_cursor_position(Position::new(
+            input_area.x + 2 + Line::raw(self.query.as_str()).width() as u16,
+            input_area.y,
+        ));
+
+        let items = self
+            .docs
+            .iter()
+            .map(|doc| {
+                ListItem::new(vec![
+                    Line::from(doc.title.as_str()).bold(),
+                    Line::from(format!(
+                        "  {}  {}",
+                        doc.modified_at.format("%Y-%m-%d %H:%M"),
+                        doc.source
+                    ))
+                    .dim(),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(items)
+            .block(Block::bordered().title(format!(" {} results ", self.count)))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, list_area, &mut self.list_state);
+
+        let (title, text) = match self.preview.as_ref() {
+            Some(x) => (x.location.clone(), highlight(x)),
+            None => (String::new(), Text::default()),
+        };
+        let preview = Paragraph::new(text)
+            .block(Block::bordered().title(format!(" {} ", title)))
+            .scroll((self.scroll, 0));
+        frame.render_widget(preview, preview_area);
+
+        let status = Line::from(vec![
+            Span::raw(self.status.as_str()),
+            Span::raw(
+                "  Enter: open  Tab: index  C-s: source  C-t: since  C-f/C-b: scroll  Esc: quit",
+            )
+            .dim(),
+        ]);
+        frame.render_widget(Paragraph::new(status), status_area);
+    }
+}
+
+/// Highlights the matching terms in the lines of the preview.
+fn highlight(preview: &Preview) -> Text<'_> {
+    let mut ranges = HashMap::<usize, Vec<(usize, usize)>>::new();
+    for x in preview.locations.iter() {
+        ranges
+            .entry(x.line)
+            .or_default()
+            .push((x.column - 1, x.column - 1 + x.length));
+    }
+
+    preview
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(n, line)| {
+            let Some(ranges) = ranges.get(&(n + 1)) else {
+                return Line::raw(line.as_str());
+            };
+            let mut spans = Vec::<Span>::new();
+            let mut start_from = 0;
+            for (start, end) in ranges {
+                if *start < start_from || *end > line.len() {
+                    continue;
+                }
+                spans.push(Span::raw(&line[start_from..*start]));
+                spans.push(Span::raw(&line[*start..*end]).red().bold());
+                start_from = *end;
+            }
+            spans.push(Span::raw(&line[start_from..]));
+            Line::from(spans)
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, warn};
+use serde::Serialize;
+use tantivy::directory::error::LockError;
+use tantivy::{Index, IndexReader, TantivyError};
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::fuzzy::Fuzzy;
+use crate::index::{Indexer, open_index};
+use crate::locate::Locator;
+use crate::path::PathExt;
+use crate::query::Query;
+use crate::search::{
+    Doc, Operator, SearchOptions, SearchResults, SnippetOptions, Sort, get_document_with,
+    open_reader, search_with, similar,
+};
+use crate::synonym::Synonyms;
+use crate::tokenizer::Tokenizers;
+
+/// Number of times to try again to get the writer of an index held by another process.
+const WRITER_RETRIES: usize = 5;
+
+const WRITER_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// An index opened with its reader and the options of its schema.
+struct Target {
+    index: Index,
+    reader: IndexReader,
+    synonyms: Option<Synonyms>,
+    fuzzy: Fuzzy,
+}
+
+/// Number of documents and segments of an index.
+#[derive(Debug, Serialize)]
+pub struct IndexStats {
+    pub name: String,
+    pub docs: u64,
+    pub segments: usize,
+    pub sources: HashMap<String, PathBuf>,
+}
+
+/// The indexes of a config file, opened on first use and kept open to search repeatedly.
+pub struct Workspace {
+    config: Config,
+    tokenizers: Tokenizers,
+    targets: Mutex<HashMap<String, Arc<Target>>>,
+}
+
+impl Workspace {
+    pub fn new(config: Config) -> Self {
+        Self {
+            tokenizers: Tokenizers::new(config.tokenizers.clone()),
+            config,
+            targets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the names of all indexes in the config file, sorted.
+    pub fn all_index_names(&self) -> Vec<String> {
+        let mut index_names = self.config.indexes.keys().cloned().collect::<Vec<_>>();
+        index_names.sort();
+        index_names
+    }
+
+    /// Returns the given names of the indexes or the default index, sorted and deduplicated.
+    pub fn index_names(&self, indexes: &[String]) -> Result<Vec<String>, Error> {
+        let mut index_names = match indexes.is_empty() {
+            true => vec![
+                self.config
+                    .get_default_search_index_name()
+                    .map_err(Error::NoIndex)?,
+            ],
+            false => indexes.to_vec(),
+        };
+        index_names.sort();
+        index_names.dedup();
+
+        match index_names
+            .iter()
+            .find(|x| !self.config.indexes.contains_key(*x))
+        {
+            Some(x) => Err(Error::IndexNotFound(x.clone())),
+            None => Ok(index_names),
+        }
+    }
+
+    fn target(&self, index_name: &str) -> Result<Arc<Target>, Error> {
+        let mut targets = self.targets.lock().unwrap();
+        if let Some(x) = targets.get(index_name) {
+            return Ok(x.clone());
+        }
+
+        let index = open_index(&self.config, &self.tokenizers, index_name)?;
+        let target = Arc::new(self.open_target(index_name, index)?);
+        targets.insert(index_name.to_string(), target.clone());
+        Ok(target)
+    }
+
+    fn open_target(&self, index_name: &str, index: Index) -> Result<Target, Error> {
+        let index_config = self
+            .config
+            .indexes
+            .get(index_name)
+            .ok_or_else(|| Error::IndexNotFound(index_name.to_string()))?;
+        let schema_config = self.config.get_schema(index_config.schema.as_str())?;
+        Ok(Target {
+            reader: open_reader(&index)?,
+            index,
+            synonyms: schema_config
+                .synonyms
+                .as_ref()
+                .map(Synonyms::from_config)
+                .transpose()?,
+            fuzzy: schema_config
+                .fuzzy
+                .as_ref()
+                .map(Fuzzy::from)
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Uses the index, such as an index in memory, for the index of the name in the config file
+    /// instead of opening it from its path.
+    pub fn set_index(&self, index_name: &str, index: Index) -> Result<(), Error> {
+        let target = Arc::new(self.open_target(index_name, index)?);
+        self.targets
+            .lock()
+            .unwrap()
+            .insert(index_name.to_string(), target);
+        Ok(())
+    }
+
+    /// Returns the index, which picks up the commits of other writers shortly.
+    pub fn index(&self, index_name: &str) -> Result<Index, Error> {
+        Ok(self.target(index_name)?.index.clone())
+    }
+
+    /// Searches the indexes of the query and merges the results into one ranking.
+    pub fn search(&self, query: &Query) -> Result<SearchResults, Error> {
+        let config = &self.config;
+        let index_names = self.index_names(&query.indexes)?;
+
+        let limit = query
+            .limit
+            .unwrap_or_else(|| config.get_default_search_limit());
+        let snippet = match query
+            .snippets
+            .unwrap_or_else(|| config.get_default_search_snippets())
+        {
+            0 => None,
+            count => Some(SnippetOptions {
+                max_chars: query
+                    .snippet_length
+                    .unwrap_or_else(|| config.get_default_search_snippet_length()),
+                count,
+            }),
+        };
+        let sort = query
+            .sort
+            .unwrap_or_else(|| config.get_default_search_sort());
+        let minimum_should_match = query
+            .minimum_should_match
+            .or_else(|| config.get_default_search_minimum_should_match());
+        let operator = match (query.operator, query.minimum_should_match) {
+            (Some(x), _) => x,
+            (None, Some(_)) => Operator::Or,
+            (None, None) => config.get_default_search_operator(),
+        };
+        let recency = match query.recency {
+            Some(x) => x,
+            None if config.get_default_search_recency_enabled() => {
+                Some(config.get_default_search_recency()?)
+            }
+            None => None,
+        };
+
+        // Each index returns documents up to the offset, which are skipped after merging.
+        let (index_offset, merge_offset) = match index_names.len() {
+            1 => (query.offset, 0),
+            _ => (0, query.offset),
+        };
+
+        let mut results = Vec::<SearchResults>::new();
+        for index_name in index_names.iter() {
+            let target = self.target(index_name)?;
+            let options = SearchOptions::new(limit + merge_offset)
+                .set_offset(index_offset)
+                .set_synonyms(target.synonyms.clone())
+                .set_fuzzy(query.fuzzy.then(|| target.fuzzy.clone()))
+                .set_snippet(snippet)
+                .set_filter(query.filter.clone())
+                .set_sort(sort, query.reverse)
+                .set_explain(query.explain)
+                .set_strict(query.strict)
+                .set_operator(operator, minimum_should_match)
+                .set_recency(recency);
+            let index_results = search_with(
+                &target.index,
+                &target.reader.searcher(),
+                query.text.as_str(),
+                &options,
+            )?;
+            results.push(index_results.set_index_name(index_name));
+        }
+
+        Ok(SearchResults::merge(
+            results,
+            merge_offset,
+            limit,
+            sort,
+            query.reverse,
+        ))
+    }
+
+    /// Searches documents similar to the document in the indexes of the query.
+    ///
+    /// Only the indexes, the limit and the filter of the query are used.
+    pub fn similar(&self, doc: &Doc, body: &str, query: &Query) -> Result<SearchResults, Error> {
+        let index_names = self.index_names(&query.indexes)?;
+        let limit = query
+            .limit
+            .unwrap_or_else(|| self.config.get_default_search_limit());
+
+        let options = SearchOptions::new(limit).set_filter(query.filter.clone());
+        let mut results = Vec::<SearchResults>::new();
+        for index_name in index_names.iter() {
+            let target = self.target(index_name)?;
+            results.push(similar(&target.index, doc, body, &options)?.set_index_name(index_name));
+        }
+
+        Ok(SearchResults::merge(
+            results,
+            0,
+            limit,
+            Sort::Relevance,
+            false,
+        ))
+    }
+
+    /// Finds a document by the id in the indexes in order, or in all indexes if none is given.
+    pub fn find_document(&self, indexes: &[String], id: &str) -> Result<Doc, Error> {
+        let index_names = match indexes.is_empty() {
+            true => self.all_index_names(),
+            false => indexes.to_vec(),
+        };
+        for index_name in index_names {
+            let target = self.target(index_name.as_str())?;
+            if let Some(mut doc) = get_document_with(&target.index, &target.reader.searcher(), id)?
+            {
+                doc.index = index_name;
+                return Ok(doc);
+            }
+        }
+
+        Err(Error::DocumentNotFound(id.to_string()))
+    }
+
+    /// Finds the document of a file by its absolute path.
+    pub fn find_file(&self, path: &Path) -> Result<Doc, Error> {
+        for (index_name, index_config) in self.config.indexes.iter() {
+            for (source_name, source) in index_config.sources.iter() {
+                let Some(relative_path) = path.strip_prefix(source).ok().and_then(|x| x.to_str())
+                else {
+                    continue;
+                };
+                let id = format!("{}:{}", source_name, relative_path);
+                match self.find_document(std::slice::from_ref(index_name), id.as_str()) {
+                    Err(Error::DocumentNotFound(_)) => continue,
+                    x => return x,
+                }
+            }
+        }
+
+        Err(Error::DocumentNotFound(path.to_string_lossy().to_string()))
+    }
+
+    /// Indexes a file into the indexes whose sources contain it, and returns the number of
+    /// documents indexed.
+    ///
+    /// Files which are not indexed by `shunbin index`, such as hidden files, are skipped.
+    pub fn index_file(&self, path: &Path) -> Result<usize, Error> {
+        if !path.is_index_target() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        for (index_name, index_config) in self.config.indexes.iter() {
+            let sources = index_config
+                .sources
+                .iter()
+                .filter(|(_, x)| path.starts_with(x))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<HashMap<_, _>>();
+            if sources.is_empty() {
+                continue;
+            }
+
+            let index = self.index(index_name)?;
+            let mut indexer = Indexer::new();
+            let mut retries = 0;
+            loop {
+                match indexer.index_file(&index, sources.clone(), path.to_path_buf()) {
+                    // Another process, such as `shunbin index`, holds the writer of the index.
+                    Err(Error::Tantivy(TantivyError::LockFailure(LockError::LockBusy, _)))
+                        if retries < WRITER_RETRIES =>
+                    {
+                        retries += 1;
+                        debug!("The writer of {} is busy, retrying.", index_name);
+                        thread::sleep(WRITER_RETRY_INTERVAL);
+                    }
+                    Err(Error::Tantivy(TantivyError::LockFailure(LockError::LockBusy, _))) => {
+                        warn!(
+                            "Skipped indexing {:?} into {}, the writer of the index is busy.",
+                            path, index_name
+                        );
+                        break;
+                    }
+                    x => break x?,
+                }
+            }
+            count += indexer.indexed_count();
+        }
+        Ok(count)
+    }
+
+    /// Returns a locator of the terms in the texts tokenized as the body of the index.
+    pub fn locator(&self, index_name: &str, terms: Vec<String>) -> Result<Locator, Error> {
+        let index = self.index(index_name)?;
+        let analyzer = index.tokenizer_for_field(index.schema().get_field("body")?)?;
+        Ok(Locator::new(analyzer, terms))
+    }
+
+    /// Returns the number of documents and segments, and the sources of the index.
+    pub fn index_stats(&self, index_name: &str) -> Result<IndexStats, Error> {
+        let target = self.target(index_name)?;
+        let searcher = target.reader.searcher();
+        Ok(IndexStats {
+            name: index_name.to_string(),
+            docs: searcher.num_docs(),
+            segments: searcher.segment_readers().len(),
+            sources: self.config.indexes[index_name].sources.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::index::create_ram_index;
+
+    use super::*;
+
+    #[test]
+    fn skip_indexing_file_into_busy_index() {
+        let dir = std::env::temp_dir().join(format!("shunbin-busy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.md");
+        fs::write(&path, "# Auth\n").unwrap();
+
+        let config = toml::from_str::<Config>(&format!(
+            "[indexes.notes]\nschema = \"default\"\nsources = {{ notes = {:?} }}\n\n\
+             [schema.default]\nfields = {{}}\n",
+            dir
+        ))
+        .unwrap();
+        let index = create_ram_index(
+            config.get_schema("default").unwrap(),
+            &Tokenizers::new(HashMap::new()),
+        )
+        .unwrap();
+        let workspace = Workspace::new(config);
+        workspace.set_index("notes", index.clone()).unwrap();
+
+        let writer = index
+            .writer::<tantivy::TantivyDocument>(15_000_000)
+            .unwrap();
+        assert_eq!(workspace.index_file(&path).unwrap(), 0);
+        drop(writer);
+        assert_eq!(workspace.index_file(&path).unwrap(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}